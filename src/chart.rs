@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Cursor;
+use chrono::{DateTime, Duration, Utc};
+use image::{ImageOutputFormat, RgbImage};
+use plotters::prelude::*;
+use plotters::drawing::DrawingAreaErrorKind;
+use crate::models::SizeEvent;
+
+const WIDTH: u32 = 800;
+const HEIGHT: u32 = 480;
+
+/// График не нарисовался: нет данных, ошибка plotters или кодирования PNG.
+#[derive(Debug)]
+pub struct ChartError(String);
+
+impl fmt::Display for ChartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for ChartError {}
+
+impl<E: std::error::Error + Send + Sync> From<DrawingAreaErrorKind<E>> for ChartError {
+    fn from(e: DrawingAreaErrorKind<E>) -> Self { ChartError(e.to_string()) }
+}
+
+impl From<image::ImageError> for ChartError {
+    fn from(e: image::ImageError) -> Self { ChartError(e.to_string()) }
+}
+
+// Рисование идёт в spawn_blocking
+impl From<tokio::task::JoinError> for ChartError {
+    fn from(e: tokio::task::JoinError) -> Self { ChartError(e.to_string()) }
+}
+
+/// Точки графика размера пользователя: начальный размер и размер после каждого изменения.
+pub fn user_series(events: &[SizeEvent]) -> Vec<(DateTime<Utc>, f64)> {
+    let mut points = Vec::with_capacity(events.len() + 1);
+    if let Some(first) = events.first() {
+        points.push((first.at, (first.size - first.delta) as f64));
+    }
+    points.extend(events.iter().map(|e| (e.at, e.size as f64)));
+    points
+}
+
+/// Средний размер по чату после каждого изменения.
+/// Учитываются только пользователи, у которых уже было хотя бы одно изменение в текущем сезоне:
+/// обнуление сезона своей точки не даёт и выводит пользователя из среднего до следующего изменения.
+pub fn average_series(chat_events: &[SizeEvent]) -> Vec<(DateTime<Utc>, f64)> {
+    let mut sizes: HashMap<i64, i32> = HashMap::new();
+    chat_events
+        .iter()
+        .filter_map(|e| {
+            if e.is_season_reset() {
+                sizes.remove(&e.user_id);
+                return None;
+            }
+            sizes.insert(e.user_id, e.size);
+            let total: i32 = sizes.values().sum();
+            Some((e.at, total as f64 / sizes.len() as f64))
+        })
+        .collect()
+}
+
+/// Рисует линейный график размера и, если есть, среднего по чату. Возвращает PNG.
+/// Подписи на самом графике не рисуются, чтобы не тянуть шрифты: числа идут в подпись к фото.
+pub fn render_growth_chart(
+    user: &[(DateTime<Utc>, f64)],
+    average: &[(DateTime<Utc>, f64)],
+) -> Result<Vec<u8>, ChartError> {
+    let (Some(start), Some(end)) = (user.first().map(|p| p.0), user.last().map(|p| p.0)) else {
+        return Err(ChartError("нет данных для графика".to_string()));
+    };
+    // Одна точка даёт пустой диапазон, расширяем его до часа
+    let end = end.max(start + Duration::hours(1));
+    let average: Vec<_> = average.iter().filter(|(at, _)| *at >= start && *at <= end).copied().collect();
+
+    let max_size = user.iter().chain(&average).map(|p| p.1).fold(0.0, f64::max);
+    let y_max = (max_size * 1.1).max(10.0);
+
+    let mut buffer = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+        let area = root.margin(20, 20, 20, 20);
+
+        // По оси X часы от первой точки: у plotters переполняется сетка на больших timestamp
+        let hours = |at: &DateTime<Utc>| (*at - start).num_seconds() as f64 / 3600.0;
+        let mut chart = ChartBuilder::on(&area)
+            .build_cartesian_2d(0.0..hours(&end), 0.0..y_max)?;
+        // Сетку рисуем сами: встроенная сетка plotters без шрифтов не отображается.
+        // Горизонтальные линии через каждые 5 см (или кратно больше), вертикальные по дням
+        let grid = RGBColor(225, 225, 225);
+        let y_step = ((y_max / 8.0 / 5.0).ceil() * 5.0).max(5.0);
+        for i in 0..=(y_max / y_step) as usize {
+            let y = i as f64 * y_step;
+            chart.draw_series(LineSeries::new([(0.0, y), (hours(&end), y)], grid))?;
+        }
+        let days = (end - start).num_days();
+        let x_step = if days > 60 { 7 } else { 1 };
+        for day in (0..=days).step_by(x_step) {
+            let x = (day * 24) as f64;
+            chart.draw_series(LineSeries::new([(x, 0.0), (x, y_max)], grid))?;
+        }
+
+        if !average.is_empty() {
+            chart.draw_series(LineSeries::new(
+                average.iter().map(|(at, size)| (hours(at), *size)),
+                RGBColor(160, 160, 160).stroke_width(2),
+            ))?;
+        }
+
+        chart.draw_series(LineSeries::new(
+            user.iter().map(|(at, size)| (hours(at), *size)),
+            RGBColor(220, 60, 110).stroke_width(4),
+        ))?;
+        chart.draw_series(user.iter().map(|(at, size)| {
+            Circle::new((hours(at), *size), 4, RGBColor(220, 60, 110).filled())
+        }))?;
+
+        root.present()?;
+    }
+
+    let image = RgbImage::from_raw(WIDTH, HEIGHT, buffer).ok_or_else(|| ChartError("неверный размер буфера".to_string()))?;
+    let mut png = Cursor::new(Vec::new());
+    image.write_to(&mut png, ImageOutputFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ChangeSource;
+
+    fn event(user_id: i64, days_ago: i64, delta: i32, size: i32) -> SizeEvent {
+        SizeEvent {
+            chat_id: 1,
+            user_id,
+            at: Utc::now() - Duration::days(days_ago),
+            source: ChangeSource::Roll,
+            delta,
+            size,
+        }
+    }
+
+    #[test]
+    fn series_start_from_size_before_first_change() {
+        let events = [event(1, 3, 5, 5), event(1, 2, -2, 3)];
+        let sizes: Vec<_> = user_series(&events).iter().map(|p| p.1).collect();
+        assert_eq!(sizes, [0.0, 5.0, 3.0]);
+    }
+
+    #[test]
+    fn average_counts_only_active_users() {
+        let events = [event(1, 3, 4, 4), event(2, 2, 8, 8), event(1, 1, 2, 6)];
+        let average: Vec<_> = average_series(&events).iter().map(|p| p.1).collect();
+        assert_eq!(average, [4.0, 6.0, 7.0]);
+    }
+
+    #[test]
+    fn season_reset_leaves_no_point() {
+        let reset = SizeEvent { source: ChangeSource::Season, ..event(2, 2, -8, 0) };
+        let events = [event(1, 3, 4, 4), event(2, 3, 8, 8), reset, event(1, 1, 2, 6)];
+        let average: Vec<_> = average_series(&events).iter().map(|p| p.1).collect();
+        assert_eq!(average, [4.0, 6.0, 6.0]);
+    }
+
+    #[test]
+    fn renders_png() {
+        let events = [event(1, 3, 4, 4)];
+        let png = render_growth_chart(&user_series(&events), &average_series(&events)).unwrap();
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+}
@@ -1,17 +1,35 @@
-use rand::seq::SliceRandom;
-use teloxide::payloads::SendPhotoSetters;
-use teloxide::types::ChatId;
+use std::collections::HashMap;
+use teloxide::payloads::{
+    AnswerCallbackQuerySetters, EditMessageMediaSetters, EditMessageTextSetters, SendMessageSetters, SendPhotoSetters, SendPollSetters,
+};
+use teloxide::types::{
+    BotCommand, CallbackQuery, ChatId, InlineKeyboardButton, InlineKeyboardMarkup, InputFile, InputMedia, InputMediaPhoto, MessageId, ParseMode,
+    Poll, ReplyParameters, UserId,
+};
+use teloxide::utils::html;
 use teloxide::utils::markdown::escape;
-use teloxide::Bot;
+use teloxide::{ApiError, Bot, RequestError};
 use teloxide::macros::BotCommands;
+use teloxide::utils::command::BotCommands as _;
 use teloxide::prelude::{Message, Requester};
-use chrono::{DateTime, Local, Utc};
+use chrono::{Duration, Utc};
 use rand::Rng;
-use reqwest::Client;
-use scraper::{Html, Selector};
-use crate::config::Config;
-use crate::loader::Error;
-use crate::models::{TrainingExercise, get_training_exercises};
+use crate::chart::{average_series, render_growth_chart, user_series, ChartError};
+use crate::config::{Config, SharedConfig};
+use crate::content::ContentPack;
+use crate::cooldown::format_wait;
+use crate::duel::Duel;
+use crate::http::{HttpClient, Source};
+use crate::i18n::{t, translate, Lang};
+use crate::jokes;
+use crate::memes::{self, MemeKind};
+use crate::movies::{self, FilterError, Movie, MovieFilter};
+use rand::seq::SliceRandom;
+use crate::error::Error;
+use crate::weather;
+use crate::models::{
+    Achievement, ChangeSource, MoviePoll, SeasonResult, SeenKind, SizeEvent, TrainingExercise, UserData, is_milestone, streak_bonus, summarize,
+};
 
 #[derive(BotCommands, Clone)]
 #[command(rename_rule = "lowercase", description = "Эти команды доступны:")]
@@ -20,75 +38,172 @@ pub enum Command {
     Pisun,
     #[command(description = "Тренировка твоего писюна")]
     Train,
-    #[command(description = "Показывает топ 10 пользователей")]
+    #[command(description = "Показывает рейтинг чата")]
     Top,
     #[command(description = "Показывает текущий размер")]
     Size,
-    #[command(description = "Случайный фильм на вечер")]
-    RandomMovie,
+    #[command(description = "Случайный фильм на вечер: /randommovie [жанр] [годы] [рейтинг] [язык]")]
+    RandomMovie(String),
+    #[command(description = "Фильмы, которые чат хочет посмотреть")]
+    Watchlist,
+    #[command(description = "Голосование за фильм вечера: /movienight [число фильмов]")]
+    MovieNight(String),
     #[command(description = "Случайный анекдот")]
     Anekdot,
-    #[command(description = "Погода")]
-    Weather,
+    #[command(description = "Погода: /weather [город]")]
+    Weather(String),
     #[command(description = "Случайный мем")]
     Meme,
     #[command(description = "Случайная мудрость")]
     Wisdom,
     #[command(description = "Совет, если ты с похмелья")]
     Hangover,
+    #[command(description = "История изменений размера: /history [сколько]")]
+    History(String),
+    #[command(description = "График роста твоего писюна")]
+    Stats,
+    #[command(description = "Когда обновляются лимиты: /resettime <часовой пояс> <час>")]
+    ResetTime(String),
+    #[command(description = "Вызвать на дуэль: /duel @username <см> или ответом на сообщение")]
+    Duel(String),
+    #[command(description = "Твои достижения")]
+    Achievements,
+    #[command(description = "Текущий сезон и сколько до его конца")]
+    Season,
+    #[command(description = "Победители прошлых сезонов")]
+    HallOfFame,
+    #[command(description = "Перечитать тексты бота (только для владельцев)")]
+    Reload,
+    #[command(description = "Язык бота в этом чате")]
+    Language(String),
+    #[command(description = "Город для погоды в этом чате: /setcity <город>")]
+    SetCity(String),
+}
+
+/// Меню команд с описаниями на нужном языке, для `set_my_commands`.
+pub(crate) fn localized_commands(lang: Lang) -> Vec<BotCommand> {
+    Command::bot_commands()
+        .into_iter()
+        .map(|command| {
+            let key = format!("command.{}", command.command.trim_start_matches('/'));
+            BotCommand::new(command.command, translate(lang, &key, &[]))
+        })
+        .collect()
 }
 
-pub(crate) async fn command_handler(bot: Bot, msg: Message, cmd: Command) -> Result<(), Error> {
-    let mut config = Config::new().await;
-    match cmd {
-        Command::Pisun => pisun_handler(bot, msg, &mut config).await,
-        Command::Size => size_handler(bot, msg, &mut config).await,
-        Command::Top => top_handler(bot, msg, &mut config).await,
-        Command::Anekdot => joke_handler(bot, msg).await,
-        Command::Train => train_handler(bot, msg, &mut config).await,
-        Command::Weather => weather_handler(bot, msg, &mut config).await,
-        Command::Meme => meme_handler(bot, msg).await,
-        Command::Wisdom => wisdom_handler(bot, msg).await,
-        Command::Hangover => hangover_handler(bot, msg).await,
-        Command::RandomMovie => random_movie_handler(bot, msg).await,
+pub(crate) async fn command_handler(bot: Bot, msg: Message, cmd: Command, config: SharedConfig, http: HttpClient) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    let from = msg.from.clone();
+    let lang = roll_over_season(&bot, &config, msg.chat.id).await?;
+
+    let result = match cmd {
+        Command::Pisun => pisun_handler(bot, msg, &config, lang).await,
+        Command::Size => size_handler(bot, msg, &config, lang).await,
+        Command::Top => top_handler(bot, msg, &config, lang).await,
+        Command::Anekdot => joke_handler(bot, msg, &config, &http, lang).await,
+        Command::Train => train_handler(bot, msg, &config, lang).await,
+        Command::Weather(city) => weather_handler(bot, msg, &config, &http, lang, &city).await,
+        Command::Meme => meme_handler(bot, msg, &http, lang).await,
+        Command::Wisdom => wisdom_handler(bot, msg, &config, lang).await,
+        Command::Hangover => hangover_handler(bot, msg, &config, lang).await,
+        Command::RandomMovie(args) => random_movie_handler(bot, msg, &config, &http, lang, &args).await,
+        Command::Watchlist => watchlist_handler(bot, msg, &config, lang).await,
+        Command::MovieNight(args) => movie_night_handler(bot, msg, &config, lang, &args).await,
+        Command::History(count) => history_handler(bot, msg, &config, lang, &count).await,
+        Command::Stats => stats_handler(bot, msg, &config, lang).await,
+        Command::ResetTime(args) => reset_time_handler(bot, msg, &config, lang, &args).await,
+        Command::Duel(args) => duel_handler(bot, msg, &config, lang, &args).await,
+        Command::Achievements => achievements_handler(bot, msg, &config, lang).await,
+        Command::Season => season_handler(bot, msg, &config, lang).await,
+        Command::HallOfFame => hall_of_fame_handler(bot, msg, &config, lang).await,
+        Command::Reload => reload_handler(bot, msg, &config, lang).await,
+        Command::Language(code) => language_handler(bot, msg, &config, lang, &code).await,
+        Command::SetCity(city) => set_city_handler(bot, msg, &config, &http, lang, &city).await,
+    };
+
+    // Имена нужны для рейтинга, обновляем их после каждой команды
+    if let Some(from) = from {
+        config.lock().await.update_profile(chat_id, &from)?;
     }
+    result
 }
 
-async fn pisun_handler(bot: Bot, msg: Message, config: &mut Config) -> Result<(), Error> {
+async fn pisun_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
     let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
-    let mut user = config.get_or_create_user(user_id).clone();
-    
-    if can_use_command(user.last_command) {
-        let change = match user.pisun {
-            0 => generate_random_change(0, 10),
-            _ => generate_random_change(-10, 10),
-        };
-        let message = get_roll_message(change);
-        
-        user.pisun += change;
-        user.last_command = Utc::now();
 
-        if user.pisun < 0 {
-            user.pisun = 0;
-            bot.send_message(msg.chat.id, "Мои соболезнования. Сегодня у тебя произошла страшная трагедия, твой писюн отпал.").await?;
+    // Чтение и запись пользователя под одной блокировкой, чтобы не потерять параллельные изменения
+    let outcome = {
+        let mut config = config.lock().await;
+        let mut user = config.get_or_create_user(chat_id, user_id)?;
+        let reset = config.get_daily_reset(chat_id)?;
+        let now = Utc::now();
+
+        if !reset.is_available(user.last_command, now) {
+            Err(reset.time_until_reset(now))
         } else {
-            bot.send_message(msg.chat.id, message).await?;
+            let broken = user.roll_streak.advance(reset.days_between(user.last_command, now));
+            let streak = user.roll_streak.current;
+
+            // Серия поднимает нижнюю границу замера, а пропущенный день стоит сантиметров
+            let bonus = streak_bonus(streak);
+            let roll = match user.pisun {
+                0 => generate_random_change(bonus.min(10), 10),
+                _ => generate_random_change((bonus - 10).min(10), 10),
+            };
+            let penalty = broken.map_or(0, streak_bonus);
+            let change = roll - penalty;
+            let mut message = config.content(lang).roll_message(roll);
+            let before = user.pisun;
+
+            user.pisun += change;
+            user.last_command = now;
+
+            if user.pisun < 0 {
+                user.pisun = 0;
+                message = t!(lang, "pisun.fell_off");
+            }
+
+            if let (Some(days), true) = (broken, penalty > 0) {
+                message.push_str("\n\n");
+                message.push_str(&t!(lang, "pisun.streak_broken", days = days, cm = penalty));
+            }
+            if is_milestone(streak) {
+                message.push_str("\n\n");
+                message.push_str(&t!(lang, "pisun.milestone", days = streak, bonus = bonus));
+            } else if streak > 1 {
+                message.push_str("\n\n");
+                message.push_str(&t!(lang, "pisun.streak", days = streak));
+            }
+
+            // Размер и событие истории сохраняются вместе, достижения — только после них
+            let event = SizeEvent::new(chat_id, user_id, ChangeSource::Roll, before, user.pisun);
+            config.save_batch(&[user], std::slice::from_ref(&event))?;
+            let unlocked = config.unlock_achievements(&event, change, Some(roll))?;
+            Ok((message, unlocked))
         }
+    };
 
-        config.update_user(user_id, |u| *u = user);
-    } else {
-        send_cooldown_message(&bot, msg.chat.id).await?;
+    match outcome {
+        Ok((message, unlocked)) => {
+            bot.send_message(msg.chat.id, message).await?;
+            announce_achievements(&bot, &msg, lang, &unlocked).await?;
+        }
+        Err(wait) => {
+            send_cooldown_message(&bot, msg.chat.id, lang, wait).await?;
+        }
     }
     
     Ok(())
 }
 
-async fn size_handler(bot: Bot, msg: Message, config: &mut Config) -> Result<(), Error> {
+async fn size_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
     let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
-    let user = config.get_or_create_user(user_id).clone();
-    
-    let all_users = config.get_all_users();
-    let mut sorted_users: Vec<_> = all_users.values().collect();
+    let (user, mut sorted_users, reset) = {
+        let mut config = config.lock().await;
+        (config.get_or_create_user(chat_id, user_id)?, config.get_chat_users(chat_id)?, config.get_daily_reset(chat_id)?)
+    };
     sorted_users.sort_by_key(|u| std::cmp::Reverse(u.pisun));
 
     let user_rank = sorted_users
@@ -97,372 +212,1199 @@ async fn size_handler(bot: Bot, msg: Message, config: &mut Config) -> Result<(),
         .map(|rank| rank + 1)
         .unwrap_or(sorted_users.len() + 1);
 
-    let message = match user.pisun {
-        0 => format!("На данный момент у тебя нет писюна, неудачник! Ты занимаешь {} место в рейтинге.", user_rank),
-        _ => format!("Текущий размер твоего писюна аж {} см. Ты занимаешь {} место в рейтинге.", user.pisun, user_rank),
+    let mut message = match user.pisun {
+        0 => t!(lang, "size.none", rank = user_rank),
+        _ => t!(lang, "size.current", cm = user.pisun, rank = user_rank),
     };
+    let now = Utc::now();
+    message.push_str("\n\n");
+    message.push_str(&t!(
+        lang,
+        "size.streaks",
+        rolls = user.roll_streak.alive(reset.days_between(user.last_command, now)),
+        best_rolls = user.roll_streak.best,
+        trains = user.train_streak.alive(reset.days_between(user.last_train, now)),
+        best_trains = user.train_streak.best,
+    ));
+    let wins = season_wins(config, chat_id).await?.get(&user_id).copied().unwrap_or(0);
+    if wins > 0 {
+        message.push('\n');
+        message.push_str(&t!(lang, "size.champion", wins = wins));
+    }
     bot.send_message(msg.chat.id, message).await?;
     
     Ok(())
 }
 
-async fn top_handler(bot: Bot, msg: Message, config: &Config) -> Result<(), Error> {
-    let users = config.get_all_users();
-    let mut users: Vec<_> = users.values().collect();
-    users.sort_by_key(|u| std::cmp::Reverse(u.pisun));
+const TOP_PAGE_SIZE: usize = 10;
+
+async fn top_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let users = sorted_chat_users(config, msg.chat.id.0).await?;
+    let wins = season_wins(config, msg.chat.id.0).await?;
+    let (text, keyboard) = render_top_page(lang, &users, &wins, 0);
+
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
     
+    Ok(())
+}
+
+// Конец сезона замечаем при первой команде или нажатии кнопки после него и сразу объявляем итоги,
+// чтобы в завершённом сезоне нельзя было листать рейтинг и драться. Возвращает язык чата
+async fn roll_over_season(bot: &Bot, config: &SharedConfig, chat_id: ChatId) -> Result<Lang, Error> {
+    let (finished, lang) = {
+        let mut config = config.lock().await;
+        (config.finish_season(chat_id.0, Utc::now())?, config.get_language(chat_id.0)?)
+    };
+    if let Some(result) = finished {
+        announce_season(bot, chat_id, lang, &result).await?;
+    }
+    Ok(lang)
+}
+
+/// Обрабатывает нажатия на инлайн-кнопки. Данные кнопки начинаются с префикса функции, например `top:`.
+pub(crate) async fn callback_handler(bot: Bot, q: CallbackQuery, config: SharedConfig, http: HttpClient) -> Result<(), Error> {
+    let data = q.data.clone().unwrap_or_default();
+    let lang = match &q.message {
+        Some(message) => roll_over_season(&bot, &config, message.chat().id).await?,
+        None => config.lock().await.get_default_language(),
+    };
+
+    // Обработчик кнопки может вернуть короткое всплывающее уведомление
+    let notice = if let Some(action) = data.strip_prefix("top:") {
+        top_callback(&bot, &q, lang, action, &config).await?
+    } else if let Some(action) = data.strip_prefix("hof:") {
+        hall_of_fame_callback(&bot, &q, lang, action, &config).await?
+    } else if let Some(action) = data.strip_prefix("duel:") {
+        duel_callback(&bot, &q, lang, action, &config).await?
+    } else if let Some(code) = data.strip_prefix("lang:") {
+        language_callback(&bot, &q, code, &config).await?
+    } else if let Some(filter) = data.strip_prefix("movie:next:") {
+        movie_callback(&bot, &q, lang, filter, &config, &http).await?
+    } else if let Some(movie_id) = data.strip_prefix("movie:watch:") {
+        watchlist_callback(&q, lang, movie_id, &config, &http).await?
+    } else {
+        None
+    };
+
+    let mut answer = bot.answer_callback_query(q.id);
+    if let Some(notice) = notice {
+        answer = answer.text(notice);
+    }
+    answer.await?;
+    Ok(())
+}
+
+async fn top_callback(bot: &Bot, q: &CallbackQuery, lang: Lang, action: &str, config: &SharedConfig) -> Result<Option<String>, Error> {
+    let Some(message) = &q.message else {
+        return Ok(None);
+    };
+    let users = sorted_chat_users(config, message.chat().id.0).await?;
+
+    let page = match action {
+        "me" => match users.iter().position(|u| u.user_id == q.from.id.0 as i64) {
+            Some(rank) => rank / TOP_PAGE_SIZE,
+            None => return Ok(Some(t!(lang, "top.not_ranked"))),
+        },
+        page => page.strip_prefix("page:").and_then(|p| p.parse().ok()).unwrap_or(0),
+    };
+
+    let wins = season_wins(config, message.chat().id.0).await?;
+    let (text, keyboard) = render_top_page(lang, &users, &wins, page);
+    let result = bot.edit_message_text(message.chat().id, message.id(), text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await;
+
+    match result {
+        // Нажали на ту же страницу, что уже показана
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Пользователи чата по убыванию размера; при равенстве порядок по id, чтобы страницы не прыгали
+async fn sorted_chat_users(config: &SharedConfig, chat_id: i64) -> Result<Vec<UserData>, Error> {
+    let mut users = config.lock().await.get_chat_users(chat_id)?;
+    users.sort_by_key(|u| (std::cmp::Reverse(u.pisun), u.user_id));
+    Ok(users)
+}
+
+// Сколько сезонов выиграл каждый участник чата
+async fn season_wins(config: &SharedConfig, chat_id: i64) -> Result<HashMap<i64, usize>, Error> {
+    let seasons = config.lock().await.get_chat_seasons(chat_id)?;
+    let mut wins = HashMap::new();
+    for winner in seasons.iter().filter_map(SeasonResult::winner) {
+        *wins.entry(winner.user_id).or_default() += 1;
+    }
+    Ok(wins)
+}
+
+fn champion_badge(wins: usize) -> String {
+    match wins {
+        0 => String::new(),
+        1 => " 🏆".to_string(),
+        n => format!(" 🏆×{}", n),
+    }
+}
+
+fn render_top_page(lang: Lang, users: &[UserData], wins: &HashMap<i64, usize>, page: usize) -> (String, InlineKeyboardMarkup) {
+    let pages = users.len().div_ceil(TOP_PAGE_SIZE).max(1);
+    let page = page.min(pages - 1);
+
     let top = users.iter()
-        .take(10)
         .enumerate()
-        .map(|(i, u)| format!("{}. {} см.", i + 1, u.pisun))
+        .skip(page * TOP_PAGE_SIZE)
+        .take(TOP_PAGE_SIZE)
+        .map(|(i, u)| t!(
+            lang,
+            "top.row",
+            place = i + 1,
+            name = mention(u),
+            badge = champion_badge(wins.get(&u.user_id).copied().unwrap_or(0)),
+            cm = u.pisun,
+        ))
         .collect::<Vec<_>>()
         .join("\n");
-    
-    let message = format!("Топ 10 самых больших писюнов:\n{}", top);
+    let text = format!("{}\n{}", t!(lang, "top.title", page = page + 1, pages = pages), top);
+
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(t!(lang, "top.back"), format!("top:page:{}", page - 1)));
+    }
+    if page + 1 < pages {
+        navigation.push(InlineKeyboardButton::callback(t!(lang, "top.forward"), format!("top:page:{}", page + 1)));
+    }
+    let keyboard = InlineKeyboardMarkup::new([
+        navigation,
+        vec![InlineKeyboardButton::callback(t!(lang, "top.where_am_i"), "top:me")],
+    ]);
+
+    (text, keyboard)
+}
+
+// Кликабельное имя пользователя для сообщений с ParseMode::Html
+fn mention(user: &UserData) -> String {
+    html::user_mention(UserId(user.user_id as u64), &user.display_name())
+}
+
+async fn history_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang, count: &str) -> Result<(), Error> {
+    const DEFAULT_COUNT: usize = 10;
+    const MAX_COUNT: usize = 50;
+
+    let chat_id = msg.chat.id.0;
+    let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
+    let count = count.trim().parse().unwrap_or(DEFAULT_COUNT).clamp(1, MAX_COUNT);
+    let (events, reset) = {
+        let mut config = config.lock().await;
+        (config.get_user_events(chat_id, user_id)?, config.get_daily_reset(chat_id)?)
+    };
+    let tz = reset.timezone;
+
+    if events.is_empty() {
+        bot.send_message(msg.chat.id, t!(lang, "history.empty")).await?;
+        return Ok(());
+    }
+
+    let recent = events.iter()
+        .rev()
+        .take(count)
+        .map(|e| t!(
+            lang,
+            "history.row",
+            time = e.at.with_timezone(&tz).format("%d.%m %H:%M"),
+            emoji = get_source_emoji(e.source),
+            source = t!(lang, format!("source.{}", e.source.as_str())),
+            delta = format!("{:+}", e.delta),
+            size = e.size,
+        ))
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let summary = summarize(&events, &tz);
+    let mut message = format!("{}\n{}\n", t!(lang, "history.title"), recent);
+    if let Some((day, delta)) = summary.best_day {
+        message.push('\n');
+        message.push_str(&t!(lang, "history.best_day", date = day.format("%d.%m.%Y"), delta = format!("{:+}", delta)));
+    }
+    if let Some((day, delta)) = summary.worst_day {
+        message.push('\n');
+        message.push_str(&t!(lang, "history.worst_day", date = day.format("%d.%m.%Y"), delta = format!("{:+}", delta)));
+    }
+    message.push('\n');
+    message.push_str(&t!(lang, "history.longest", growth = summary.longest_growth, decline = summary.longest_decline));
+    match summary.current_streak {
+        streak if streak > 1 => message.push_str(&format!("\n{}", t!(lang, "history.growing", count = streak))),
+        streak if streak < -1 => message.push_str(&format!("\n{}", t!(lang, "history.shrinking", count = -streak))),
+        _ => {}
+    }
+
     bot.send_message(msg.chat.id, message).await?;
-    
     Ok(())
 }
 
-async fn joke_handler(bot: Bot, msg: Message) -> Result<(), Error> {
-    match get_random_joke().await {
-        Ok(joke) => {
-            bot.send_message(msg.chat.id, joke).await?;
+async fn stats_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
+    let (chat_events, reset) = {
+        let mut config = config.lock().await;
+        (config.get_chat_events(chat_id)?, config.get_daily_reset(chat_id)?)
+    };
+    // Обнуления сезона на графике и в подписи не показываем
+    let events: Vec<_> = chat_events.iter().filter(|e| e.user_id == user_id && !e.is_season_reset()).cloned().collect();
+
+    if events.is_empty() {
+        bot.send_message(msg.chat.id, t!(lang, "stats.empty")).await?;
+        return Ok(());
+    }
+
+    let user_points = user_series(&events);
+    let average_points = average_series(&chat_events);
+    let average = average_points.last().map(|p| p.1).unwrap_or(0.0);
+    // Рисование занимает заметное время, не держим на нём рабочий поток
+    let png = tokio::task::spawn_blocking(move || render_growth_chart(&user_points, &average_points)).await.map_err(ChartError::from)??;
+
+    let first = &events[0];
+    let last = &events[events.len() - 1];
+    let max_size = events.iter().map(|e| e.size).max().unwrap_or(0);
+    let caption = t!(
+        lang,
+        "stats.caption",
+        since = first.at.with_timezone(&reset.timezone).format("%d.%m.%Y"),
+        before = first.size - first.delta,
+        now = last.size,
+        record = max_size,
+        changes = events.len(),
+        average = format!("{:.1}", average),
+    );
+
+    bot.send_photo(msg.chat.id, InputFile::memory(png).file_name("stats.png"))
+        .caption(caption)
+        .await?;
+    Ok(())
+}
+
+async fn reset_time_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang, args: &str) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    let args: Vec<&str> = args.split_whitespace().collect();
+
+    if args.is_empty() {
+        let reset = config.lock().await.get_daily_reset(chat_id)?;
+        let message = t!(
+            lang,
+            "resettime.current",
+            hour = format!("{:02}", reset.reset_hour),
+            timezone = reset.timezone.name(),
+            wait = format_wait(lang, reset.time_until_reset(Utc::now())),
+        );
+        bot.send_message(msg.chat.id, message).await?;
+        return Ok(());
+    }
+
+    if !is_chat_admin(&bot, &msg).await? {
+        bot.send_message(msg.chat.id, t!(lang, "resettime.admins_only")).await?;
+        return Ok(());
+    }
+
+    let Ok(timezone) = args[0].parse::<chrono_tz::Tz>() else {
+        bot.send_message(msg.chat.id, t!(lang, "resettime.unknown_timezone")).await?;
+        return Ok(());
+    };
+    let reset_hour = match args.get(1).map(|hour| hour.parse::<u32>()) {
+        None => 0,
+        Some(Ok(hour)) if hour < 24 => hour,
+        Some(_) => {
+            bot.send_message(msg.chat.id, t!(lang, "resettime.bad_hour")).await?;
+            return Ok(());
         }
-        Err(e) => {
-            eprintln!("Ошибка при получении анекдота: {}", e);
-            bot.send_message(msg.chat.id, "Не удалось получить анекдот").await?;
+    };
+
+    config.lock().await.update_chat_settings(chat_id, |settings| {
+        settings.timezone = Some(timezone.name().to_string());
+        settings.reset_hour = Some(reset_hour);
+    })?;
+    bot.send_message(
+        msg.chat.id,
+        t!(lang, "resettime.done", hour = format!("{:02}", reset_hour), timezone = timezone.name())
+    ).await?;
+    Ok(())
+}
+
+async fn set_city_handler(bot: Bot, msg: Message, config: &SharedConfig, http: &HttpClient, lang: Lang, city: &str) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    let city = city.trim();
+
+    if city.is_empty() {
+        let saved = config.lock().await.get_location(chat_id)?;
+        let name = saved.map(|location| location.name).unwrap_or_else(|| t!(lang, "weather.default_city"));
+        bot.send_message(msg.chat.id, t!(lang, "setcity.current", city = name)).await?;
+        return Ok(());
+    }
+
+    if !is_chat_admin(&bot, &msg).await? {
+        bot.send_message(msg.chat.id, t!(lang, "setcity.admins_only")).await?;
+        return Ok(());
+    }
+
+    let location = match weather::geocode(http, city, lang).await {
+        Ok(Some(location)) => location,
+        Ok(None) => {
+            bot.send_message(msg.chat.id, t!(lang, "weather.city_not_found", query = city)).await?;
+            return Ok(());
         }
+        Err(e) => return report_source_failure(&bot, msg.chat.id, lang, Source::Geocoding, &e).await,
+    };
+
+    let message = t!(lang, "setcity.done", city = location.name, timezone = location.timezone);
+    config.lock().await.update_chat_settings(chat_id, |settings| {
+        settings.location = Some(location);
+    })?;
+    bot.send_message(msg.chat.id, message).await?;
+    Ok(())
+}
+
+async fn language_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang, code: &str) -> Result<(), Error> {
+    let code = code.trim();
+    if code.is_empty() {
+        let buttons = Lang::ALL.map(|option| InlineKeyboardButton::callback(option.native_name(), format!("lang:{}", option.code())));
+        bot.send_message(msg.chat.id, t!(lang, "language.current", language = lang.native_name()))
+            .reply_markup(InlineKeyboardMarkup::new([buttons]))
+            .await?;
+        return Ok(());
     }
+
+    let message = match Lang::from_code(code) {
+        None => t!(lang, "language.unknown", codes = Lang::ALL.map(Lang::code).join(", ")),
+        Some(_) if !is_chat_admin(&bot, &msg).await? => t!(lang, "language.admins_only"),
+        Some(new) => {
+            set_language(config, msg.chat.id.0, new).await?;
+            t!(new, "language.done")
+        }
+    };
+    bot.send_message(msg.chat.id, message).await?;
     Ok(())
 }
 
-async fn train_handler(bot: Bot, msg: Message, config: &mut Config) -> Result<(), Error> {
-    let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
-    let mut user = config.get_or_create_user(user_id).clone();
-    
+async fn language_callback(bot: &Bot, q: &CallbackQuery, code: &str, config: &SharedConfig) -> Result<Option<String>, Error> {
+    let (Some(message), Some(new)) = (&q.message, Lang::from_code(code)) else {
+        return Ok(None);
+    };
+    let chat = message.chat();
+    if !chat.is_private() && !bot.get_chat_member(chat.id, q.from.id).await?.is_privileged() {
+        let lang = config.lock().await.get_language(chat.id.0)?;
+        return Ok(Some(t!(lang, "language.admins_only")));
+    }
+
+    set_language(config, chat.id.0, new).await?;
+    bot.edit_message_text(chat.id, message.id(), t!(new, "language.done")).await?;
+    Ok(None)
+}
+
+async fn set_language(config: &SharedConfig, chat_id: i64, lang: Lang) -> Result<(), Error> {
+    config.lock().await.update_chat_settings(chat_id, |settings| {
+        settings.language = Some(lang.code().to_string());
+    })?;
+    Ok(())
+}
+
+// В личке настройки может менять сам пользователь, в группах — только админы
+async fn is_chat_admin(bot: &Bot, msg: &Message) -> Result<bool, Error> {
+    if msg.chat.is_private() {
+        return Ok(true);
+    }
+    let Some(from) = &msg.from else {
+        return Ok(false);
+    };
+    let member = bot.get_chat_member(msg.chat.id, from.id).await?;
+    Ok(member.is_privileged())
+}
+
+async fn season_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
     let now = Utc::now();
-    let can_train = now.date_naive() > user.last_train.date_naive();
+    let (season, reset) = config.lock().await.get_season(chat_id, now)?;
+    let users = sorted_chat_users(config, chat_id).await?;
+
+    let leaders = users.iter()
+        .filter(|u| u.pisun > 0)
+        .take(3)
+        .zip(["🥇", "🥈", "🥉"])
+        .map(|(u, medal)| t!(lang, "season.leader", medal = medal, name = mention(u), cm = u.pisun))
+        .collect::<Vec<_>>();
+    let message = t!(
+        lang,
+        "season.current",
+        start = season.start.format("%d.%m.%Y"),
+        end = (season.end - Duration::days(1)).format("%d.%m.%Y"),
+        wait = format_wait(lang, reset.day_start(season.end) - now),
+        leaders = if leaders.is_empty() { t!(lang, "season.no_leaders") } else { leaders.join("\n") },
+    );
 
-    if can_train {
-        let (exercise, result) = generate_training_exercise();
-        let (change, message) = process_training_result(result, user.pisun);
+    bot.send_message(msg.chat.id, message).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
 
-        user.pisun = (user.pisun + change).max(0);
-        user.last_train = now;
+/// Объявляет итоги только что завершённого сезона.
+async fn announce_season(bot: &Bot, chat_id: ChatId, lang: Lang, result: &SeasonResult) -> Result<(), Error> {
+    let message = t!(lang, "season.finished", results = render_season(lang, result));
+    bot.send_message(chat_id, message).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
 
-        let response = format!(
-            "{}\n\n{}",
-            exercise.description,
-            message
-        );
+const HALL_OF_FAME_PAGE_SIZE: usize = 5;
 
-        bot.send_message(msg.chat.id, response).await?;
-        config.update_user(user_id, |u| *u = user);
-    } else {
-        bot.send_message(msg.chat.id, "Ты уже тренировался сегодня! Возвращайся завтра 💪🍆").await?;
+async fn hall_of_fame_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let seasons = config.lock().await.get_chat_seasons(msg.chat.id.0)?;
+    if seasons.is_empty() {
+        bot.send_message(msg.chat.id, t!(lang, "halloffame.empty")).await?;
+        return Ok(());
     }
-    
+
+    let (text, keyboard) = render_hall_of_fame_page(lang, &seasons, 0);
+    bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
     Ok(())
 }
-async fn weather_handler(bot: Bot, msg: Message, config: &mut Config) -> Result<(), Error> {
-    let url = "https://api.open-meteo.com/v1/forecast?latitude=55&longitude=73.70&current=temperature_2m,relative_humidity_2m,apparent_temperature,is_day,precipitation,rain,showers,snowfall,weathercode,windspeed_10m&hourly=temperature_2m,precipitation_probability,weathercode&daily=temperature_2m_max,temperature_2m_min,sunrise,sunset&wind_speed_unit=ms&timeformat=unixtime&timezone=auto&forecast_days=3";
 
-    let response = reqwest::get(url).await.unwrap();
+async fn hall_of_fame_callback(bot: &Bot, q: &CallbackQuery, lang: Lang, action: &str, config: &SharedConfig) -> Result<Option<String>, Error> {
+    let Some(message) = &q.message else {
+        return Ok(None);
+    };
+    let page = action.strip_prefix("page:").and_then(|p| p.parse().ok()).unwrap_or(0);
+    let seasons = config.lock().await.get_chat_seasons(message.chat().id.0)?;
+
+    let (text, keyboard) = render_hall_of_fame_page(lang, &seasons, page);
+    let result = bot.edit_message_text(message.chat().id, message.id(), text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await;
+
+    match result {
+        Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Сезоны от новых к старым, по HALL_OF_FAME_PAGE_SIZE на страницу
+fn render_hall_of_fame_page(lang: Lang, seasons: &[SeasonResult], page: usize) -> (String, InlineKeyboardMarkup) {
+    let pages = seasons.len().div_ceil(HALL_OF_FAME_PAGE_SIZE).max(1);
+    let page = page.min(pages - 1);
+
+    let list = seasons.iter()
+        .rev()
+        .skip(page * HALL_OF_FAME_PAGE_SIZE)
+        .take(HALL_OF_FAME_PAGE_SIZE)
+        .map(|season| render_season(lang, season))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let text = format!("{}\n\n{}", t!(lang, "halloffame.title", page = page + 1, pages = pages), list);
+
+    let mut navigation = Vec::new();
+    if page > 0 {
+        navigation.push(InlineKeyboardButton::callback(t!(lang, "halloffame.newer"), format!("hof:page:{}", page - 1)));
+    }
+    if page + 1 < pages {
+        navigation.push(InlineKeyboardButton::callback(t!(lang, "halloffame.older"), format!("hof:page:{}", page + 1)));
+    }
+
+    (text, InlineKeyboardMarkup::new([navigation]))
+}
+
+// Даты сезона и тройка призёров
+fn render_season(lang: Lang, result: &SeasonResult) -> String {
+    let podium = result.standings.iter()
+        .take(3)
+        .zip(["🥇", "🥈", "🥉"])
+        .map(|(s, medal)| t!(
+            lang,
+            "season.leader",
+            medal = medal,
+            name = html::user_mention(UserId(s.user_id as u64), &s.name),
+            cm = s.pisun,
+        ))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let dates = t!(
+        lang,
+        "season.dates",
+        start = result.start.format("%d.%m.%Y"),
+        end = (result.end - Duration::days(1)).format("%d.%m.%Y"),
+    );
+    format!("{}\n{}", dates, podium)
+}
+
+async fn achievements_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
+    let (unlocked, reset) = {
+        let mut config = config.lock().await;
+        (config.get_user_achievements(chat_id, user_id)?, config.get_daily_reset(chat_id)?)
+    };
 
-    let weather_info: serde_json::Value = match response.status().is_success() {
-        true => {
-            let text = response.text().await.unwrap();
+    let list = Achievement::ALL
+        .iter()
+        .map(|achievement| match unlocked.iter().find(|u| u.achievement == *achievement) {
+            Some(u) => t!(
+                lang,
+                "achievements.unlocked",
+                emoji = achievement.emoji(),
+                title = achievement_text(lang, *achievement, "title"),
+                description = achievement_text(lang, *achievement, "description"),
+                date = u.at.with_timezone(&reset.timezone).format("%d.%m.%Y"),
+            ),
+            None => t!(
+                lang,
+                "achievements.locked",
+                title = achievement_text(lang, *achievement, "title"),
+                description = achievement_text(lang, *achievement, "description"),
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let title = t!(lang, "achievements.title", unlocked = unlocked.len(), total = Achievement::ALL.len());
+    let message = format!("{}\n\n{}", title, list);
 
-            let data = serde_json::from_str(&text).unwrap();
-            data
+    bot.send_message(msg.chat.id, message).await?;
+    Ok(())
+}
+
+/// Объявляет в чате только что открытые достижения.
+async fn announce_achievements(bot: &Bot, msg: &Message, lang: Lang, unlocked: &[Achievement]) -> Result<(), Error> {
+    let (Some(from), false) = (&msg.from, unlocked.is_empty()) else {
+        return Ok(());
+    };
+    let list = unlocked
+        .iter()
+        .map(|a| t!(
+            lang,
+            "achievements.announce_row",
+            emoji = a.emoji(),
+            title = achievement_text(lang, *a, "title"),
+            description = achievement_text(lang, *a, "description"),
+        ))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let name = html::user_mention(from.id, &from.full_name());
+    let text = format!("{}\n{}", t!(lang, "achievements.announce", name = name, count = unlocked.len()), list);
+
+    bot.send_message(msg.chat.id, text).parse_mode(ParseMode::Html).await?;
+    Ok(())
+}
+
+// Название или описание достижения из каталога переводов
+fn achievement_text(lang: Lang, achievement: Achievement, field: &str) -> String {
+    translate(lang, &format!("achievement.{}.{}", achievement.as_str(), field), &[])
+}
+
+async fn duel_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang, args: &str) -> Result<(), Error> {
+    let Some(from) = msg.from.clone() else {
+        return Ok(());
+    };
+    if msg.chat.is_private() {
+        bot.send_message(msg.chat.id, t!(lang, "duel.private_only")).await?;
+        return Ok(());
+    }
+
+    let (username, stake) = parse_duel_args(args);
+    let reply_target = msg.reply_to_message().and_then(|reply| reply.from.clone());
+
+    let (Some(stake), true) = (stake, username.is_some() || reply_target.is_some()) else {
+        bot.send_message(msg.chat.id, t!(lang, "duel.usage")).await?;
+        return Ok(());
+    };
+    if stake <= 0 {
+        bot.send_message(msg.chat.id, t!(lang, "duel.bad_stake")).await?;
+        return Ok(());
+    }
+    if reply_target.as_ref().is_some_and(|user| user.is_bot) {
+        bot.send_message(msg.chat.id, t!(lang, "duel.no_bots")).await?;
+        return Ok(());
+    }
+
+    let challenge = {
+        let mut config = config.lock().await;
+        let target_id = reply_target.map(|user| user.id.0 as i64);
+        create_duel(&mut config, lang, msg.chat.id.0, &from, target_id, username.as_deref(), stake)?
+    };
+    let Challenge { duel, challenger, target, timeout } = match challenge {
+        Ok(challenge) => challenge,
+        Err(refusal) => {
+            bot.send_message(msg.chat.id, refusal).parse_mode(ParseMode::Html).await?;
+            return Ok(());
+        }
+    };
+
+    let chance = Duel::challenger_win_chance(challenger.pisun, target.pisun);
+    let text = t!(
+        lang,
+        "duel.challenge",
+        challenger = mention(&challenger),
+        target = mention(&target),
+        stake = stake,
+        chance = format!("{:.0}", chance * 100.0),
+        target_chance = format!("{:.0}", (1.0 - chance) * 100.0),
+        wait = format_wait(lang, timeout),
+    );
+    let keyboard = InlineKeyboardMarkup::new([[
+        InlineKeyboardButton::callback(t!(lang, "duel.accept"), format!("duel:accept:{}", duel.id)),
+        InlineKeyboardButton::callback(t!(lang, "duel.decline"), format!("duel:decline:{}", duel.id)),
+    ]]);
+    let sent = bot.send_message(msg.chat.id, text)
+        .parse_mode(ParseMode::Html)
+        .reply_markup(keyboard)
+        .await?;
+
+    // Если на вызов так и не ответили, снимаем его и убираем кнопки
+    let config = config.clone();
+    let expired_text = t!(lang, "duel.expired", target = mention(&target), challenger = mention(&challenger));
+    tokio::spawn(async move {
+        tokio::time::sleep(timeout.to_std().unwrap_or_default()).await;
+        if config.lock().await.duels().take(duel.id).is_none() {
+            return;
         }
-        _ => {
-            panic!("Oh no! A wild error appears: {:?}", response.status());
+        if let Err(e) = bot.edit_message_text(sent.chat.id, sent.id, expired_text).parse_mode(ParseMode::Html).await {
+            log::warn!("Не удалось отменить дуэль {}: {}", duel.id, e);
         }
+    });
+
+    Ok(())
+}
+
+struct Challenge {
+    duel: Duel,
+    challenger: UserData,
+    target: UserData,
+    timeout: Duration,
+}
+
+/// Проверяет условия вызова и регистрирует дуэль. Внутренний `Err` — причина отказа для чата.
+fn create_duel(
+    config: &mut Config,
+    lang: Lang,
+    chat_id: i64,
+    from: &teloxide::types::User,
+    target_id: Option<i64>,
+    username: Option<&str>,
+    stake: i32,
+) -> Result<Result<Challenge, String>, Error> {
+    let settings = config.get_duel_settings();
+    let now = Utc::now();
+    let mut challenger = config.get_or_create_user(chat_id, from.id.0 as i64)?;
+    challenger.set_profile(from);
+
+    // Вызвать можно только того, кто уже есть в рейтинге чата
+    let users = config.get_chat_users(chat_id)?;
+    let target = users.into_iter().find(|u| match (target_id, username) {
+        (Some(id), _) => u.user_id == id,
+        (None, Some(name)) => u.username.as_ref().is_some_and(|n| n.to_lowercase() == name),
+        (None, None) => false,
+    });
+    let Some(target) = target else {
+        return Ok(Err(t!(lang, "duel.unknown_target")));
     };
 
-    let current = &weather_info["current"];
-    let daily = &weather_info["daily"];
-    let hourly = &weather_info["hourly"];
+    if target.user_id == challenger.user_id {
+        return Ok(Err(t!(lang, "duel.self")));
+    }
+    if challenger.pisun < stake {
+        return Ok(Err(t!(lang, "duel.stake_too_big", cm = challenger.pisun)));
+    }
+    if target.pisun == 0 {
+        return Ok(Err(t!(lang, "duel.nothing_to_take", name = mention(&target))));
+    }
+    if let Some(wait) = settings.cooldown_left(&challenger, now) {
+        return Ok(Err(t!(lang, "duel.cooldown", wait = format_wait(lang, wait))));
+    }
+    if let Some(wait) = settings.cooldown_left(&target, now) {
+        return Ok(Err(t!(lang, "duel.target_cooldown", name = mention(&target), wait = format_wait(lang, wait))));
+    }
+    if config.duels().is_busy(chat_id, challenger.user_id) || config.duels().is_busy(chat_id, target.user_id) {
+        return Ok(Err(t!(lang, "duel.busy")));
+    }
 
-    let current_temp = current["temperature_2m"].as_f64().unwrap();
-    let apparent_temp = current["apparent_temperature"].as_f64().unwrap();
-    let humidity = current["relative_humidity_2m"].as_f64().unwrap();
-    let is_day = current["is_day"].as_i64().unwrap();
-    let wind_speed = current["windspeed_10m"].as_f64().unwrap();
-    let weather_code = current["weathercode"].as_i64().unwrap();
+    let duel = config.duels().create(chat_id, challenger.user_id, target.user_id, stake);
+    Ok(Ok(Challenge { duel, challenger, target, timeout: settings.timeout }))
+}
 
-    let weather_description = get_weather_description(weather_code);
-    let weather_emoji = get_weather_emoji(is_day, weather_code);
+async fn duel_callback(bot: &Bot, q: &CallbackQuery, lang: Lang, action: &str, config: &SharedConfig) -> Result<Option<String>, Error> {
+    let Some(message) = &q.message else {
+        return Ok(None);
+    };
+    let Some((action, Ok(id))) = action.split_once(':').map(|(action, id)| (action, id.parse::<u64>())) else {
+        return Ok(None);
+    };
+    let presser = q.from.id.0 as i64;
+
+    let text = {
+        let mut config = config.lock().await;
+        let Some(duel) = config.duels().get(id).cloned() else {
+            return Ok(Some(t!(lang, "duel.stale")));
+        };
+        let mut challenger = config.get_or_create_user(duel.chat_id, duel.challenger_id)?;
+        let mut target = config.get_or_create_user(duel.chat_id, duel.target_id)?;
+
+        match action {
+            "decline" if presser == duel.target_id || presser == duel.challenger_id => {
+                config.duels().take(id);
+                if presser == duel.target_id {
+                    t!(lang, "duel.declined", target = mention(&target), challenger = mention(&challenger))
+                } else {
+                    t!(lang, "duel.withdrawn", challenger = mention(&challenger))
+                }
+            }
+            "accept" if presser == duel.target_id => {
+                let now = Utc::now();
+                if let Some(wait) = config.get_duel_settings().cooldown_left(&target, now) {
+                    return Ok(Some(t!(lang, "duel.cooldown", wait = format_wait(lang, wait))));
+                }
+                config.duels().take(id);
+
+                let outcome = duel.resolve(challenger.pisun, target.pisun);
+                let (winner, loser) = if outcome.winner_id == challenger.user_id {
+                    (&mut challenger, &mut target)
+                } else {
+                    (&mut target, &mut challenger)
+                };
+                let events = [
+                    SizeEvent::new(duel.chat_id, winner.user_id, ChangeSource::Duel, winner.pisun, winner.pisun + outcome.transfer),
+                    SizeEvent::new(duel.chat_id, loser.user_id, ChangeSource::Duel, loser.pisun, loser.pisun - outcome.transfer),
+                ];
+                winner.pisun += outcome.transfer;
+                loser.pisun -= outcome.transfer;
+                winner.last_duel = Some(now);
+                loser.last_duel = Some(now);
+
+                let text = if outcome.transfer > 0 {
+                    t!(
+                        lang,
+                        "duel.won",
+                        winner = mention(winner),
+                        loser = mention(loser),
+                        cm = outcome.transfer,
+                        winner_cm = winner.pisun,
+                        loser_cm = loser.pisun,
+                    )
+                } else {
+                    t!(lang, "duel.won_nothing", winner = mention(winner), loser = mention(loser))
+                };
+                // Оба участника и их история сохраняются разом, чтобы сантиметры не потерялись
+                let events = if outcome.transfer > 0 { &events[..] } else { &[] };
+                config.save_batch(&[challenger, target], events)?;
+                text
+            }
+            _ => return Ok(Some(t!(lang, "duel.not_yours"))),
+        }
+    };
 
-    let now: DateTime<Local> = Local::now();
+    bot.edit_message_text(message.chat().id, message.id(), text)
+        .parse_mode(ParseMode::Html)
+        .await?;
+    Ok(None)
+}
 
-    let max_temp = daily["temperature_2m_max"][0].as_f64().unwrap();
-    let min_temp = daily["temperature_2m_min"][0].as_f64().unwrap();
-    let sunrise = DateTime::from_timestamp(daily["sunrise"][0].as_i64().unwrap(), 0).unwrap().with_timezone(&Local);
-    let sunset = DateTime::from_timestamp(daily["sunset"][0].as_i64().unwrap(), 0).unwrap().with_timezone(&Local);
+async fn joke_handler(bot: Bot, msg: Message, config: &SharedConfig, http: &HttpClient, lang: Lang) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    let (providers, seen) = {
+        let mut config = config.lock().await;
+        (config.joke_providers(lang), config.recently_seen(chat_id, SeenKind::Joke)?)
+    };
 
-    let precipitation_prob = hourly["precipitation_probability"]
-        .as_array()
-        .unwrap()
-        .iter()
-        .take(24)
-        .map(|v| v.as_i64().unwrap())
-        .max()
-        .unwrap();
-
-    let mut weather_message = format!(
-        "{} Погода в Омске на {}\n\n\
-        Текущая температура: {:.1}°C (ощущается как {:.1}°C)\n\
-        {}\n\
-        Влажность: {}%\n\
-        Скорость ветра: {:.1} м/с\n\
-        Вероятность осадков: {}%\n\n\
-        Максимальная температура сегодня: {:.1}°C\n\
-        Минимальная температура сегодня: {:.1}°C\n\
-        Восход солнца: {}\n\
-        Закат солнца: {}\n\n\
-        Прогноз на ближайшие дни:\n{}",
-        weather_emoji,
-        now.format("%d.%m.%Y %H:%M").to_string(),
-        current_temp,
-        apparent_temp,
-        weather_description,
-        humidity,
-        wind_speed,
-        precipitation_prob,
-        max_temp,
-        min_temp,
-        sunrise.format("%H:%M").to_string(),
-        sunset.format("%H:%M").to_string(),
-        get_forecast(daily, hourly)
+    match jokes::pick(&providers, http, &seen).await {
+        Ok(joke) => {
+            config.lock().await.mark_seen(chat_id, SeenKind::Joke, joke.key)?;
+            bot.send_message(msg.chat.id, joke.text).await?;
+        }
+        Err(e) => report_source_failure(&bot, msg.chat.id, lang, Source::Jokes, &e).await?,
+    }
+    Ok(())
+}
+
+// Любой сбой внешнего источника выглядит для пользователя одинаково, подробности — только в логе
+async fn report_source_failure(bot: &Bot, chat_id: ChatId, lang: Lang, source: Source, error: &(dyn std::fmt::Display + Sync)) -> Result<(), Error> {
+    bot.send_message(chat_id, source_failure_notice(lang, source, error)).await?;
+    Ok(())
+}
+
+// Пишет сбой источника в лог и возвращает сообщение для чата или всплывающего уведомления
+fn source_failure_notice(lang: Lang, source: Source, error: &dyn std::fmt::Display) -> String {
+    log::warn!("{}: {}", source.name(), error);
+    let source_name = t!(lang, format!("external.source.{}", source.key()));
+    t!(lang, "external.failed", source = source_name)
+}
+
+async fn train_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
+
+    let (response, unlocked) = {
+        let mut config = config.lock().await;
+        let mut user = config.get_or_create_user(chat_id, user_id)?;
+
+        let reset = config.get_daily_reset(chat_id)?;
+        let now = Utc::now();
+
+        if reset.is_available(user.last_train, now) {
+            let (exercise, result) = generate_training_exercise(config.content(lang));
+            let (change, mut message) = process_training_result(lang, result, user.pisun);
+            user.train_streak.advance(reset.days_between(user.last_train, now));
+            if user.train_streak.current > 1 {
+                message.push_str("\n\n");
+                message.push_str(&t!(lang, "train.streak", days = user.train_streak.current));
+            }
+
+            let before = user.pisun;
+            user.pisun = (user.pisun + change).max(0);
+            user.last_train = now;
+
+            let event = SizeEvent::new(chat_id, user_id, ChangeSource::Training, before, user.pisun);
+            config.save_batch(&[user], std::slice::from_ref(&event))?;
+            let unlocked = config.unlock_achievements(&event, change, None)?;
+            (format!(
+                "{}\n\n{}",
+                exercise.description,
+                message
+            ), unlocked)
+        } else {
+            (t!(lang, "train.cooldown", wait = format_wait(lang, reset.time_until_reset(now))), Vec::new())
+        }
+    };
+
+    bot.send_message(msg.chat.id, response).await?;
+    announce_achievements(&bot, &msg, lang, &unlocked).await?;
+    
+    Ok(())
+}
+async fn weather_handler(bot: Bot, msg: Message, config: &SharedConfig, http: &HttpClient, lang: Lang, city: &str) -> Result<(), Error> {
+    let city = city.trim();
+    // Город из команды, иначе выбранный для чата, иначе Омск, как было всегда
+    let location = if city.is_empty() {
+        let saved = config.lock().await.get_location(msg.chat.id.0)?;
+        saved.unwrap_or_else(|| weather::default_location(t!(lang, "weather.default_city")))
+    } else {
+        match weather::geocode(http, city, lang).await {
+            Ok(Some(location)) => location,
+            Ok(None) => {
+                bot.send_message(msg.chat.id, t!(lang, "weather.city_not_found", query = city)).await?;
+                return Ok(());
+            }
+            Err(e) => return report_source_failure(&bot, msg.chat.id, lang, Source::Geocoding, &e).await,
+        }
+    };
+    let weather = match weather::forecast(http, &location).await {
+        Ok(weather) => weather,
+        Err(e) => return report_source_failure(&bot, msg.chat.id, lang, Source::Weather, &e).await,
+    };
+    let current = &weather.current;
+    let today = &weather.today;
+
+    let mut weather_message = t!(
+        lang,
+        "weather.report",
+        emoji = get_weather_emoji(current.daytime, current.code),
+        city = location.name,
+        now = weather::local_now(&location).format("%d.%m.%Y %H:%M"),
+        temperature = format!("{:.1}", current.temperature),
+        apparent = format!("{:.1}", current.apparent),
+        description = get_weather_description(lang, current.code),
+        humidity = current.humidity,
+        wind = format!("{:.1}", current.wind_speed),
+        precipitation = weather.precipitation_probability,
+        max = format!("{:.1}", today.max),
+        min = format!("{:.1}", today.min),
+        sunrise = today.sunrise.format("%H:%M"),
+        sunset = today.sunset.format("%H:%M"),
+        forecast = get_forecast(lang, &weather.next_days),
     );
 
+    let mut unlocked = Vec::new();
     if rand::thread_rng().gen_bool(0.1) {
+        let chat_id = msg.chat.id.0;
         let user_id = msg.from.clone().map(|user| user.id.0 as i64).unwrap_or(0);
-        let mut user = config.get_or_create_user(user_id).clone();
+        let mut config = config.lock().await;
+        let mut user = config.get_or_create_user(chat_id, user_id)?;
         
-        let pisun_change = calculate_pisun_change(current_temp);
+        let pisun_change = calculate_pisun_change(current.temperature);
+        let before = user.pisun;
         user.pisun = (user.pisun + pisun_change).max(0);
         
         let pisun_message = if pisun_change > 0 {
-            format!("\n\nНеожиданно! Из-за погоды твой писюн вырос на {} см!", pisun_change)
+            t!(lang, "weather.grew", cm = pisun_change)
         } else if pisun_change < 0 {
-            format!("\n\nОй-ой! Из-за погоды твой писюн уменьшился на {} см!", pisun_change.abs())
+            t!(lang, "weather.shrank", cm = pisun_change.abs())
         } else {
-            "\n\nПогода не повлияла на размер твоего писюна.".to_string()
+            t!(lang, "weather.unchanged")
         };
 
-        weather_message.push_str(&pisun_message);
-        weather_message.push_str(&format!("\nТекущий размер твоего писюна: {} см.", user.pisun));
+        weather_message.push_str(&format!("\n\n{}\n{}", pisun_message, t!(lang, "weather.size", cm = user.pisun)));
 
-        config.update_user(user_id, |u| *u = user);
+        let event = SizeEvent::new(chat_id, user_id, ChangeSource::Weather, before, user.pisun);
+        config.save_batch(&[user], std::slice::from_ref(&event))?;
+        unlocked = config.unlock_achievements(&event, pisun_change, None)?;
     }
 
     bot.send_message(msg.chat.id, weather_message).await?;
+    announce_achievements(&bot, &msg, lang, &unlocked).await?;
 
     Ok(())
 }
 
-fn can_use_command(last_command: DateTime<Utc>) -> bool {
-    let now = chrono::Local::now();
-    let last_command_date = last_command.date_naive();
-    let current_date = now.date_naive();
-
-    current_date > last_command_date 
-}
-
 fn generate_random_change(a: i32, b: i32) -> i32 {
     rand::thread_rng().gen_range(a..=b)
 }
 
-async fn send_cooldown_message(bot: &Bot, chat_id: ChatId) -> Result<Message, Error> {
-    bot.send_message(
-        chat_id,
-        "Ты уже измерял свой огрызок сегодня! Попробуй завтра 😊"
-    ).await.map_err(|e| e.into())
+async fn send_cooldown_message(bot: &Bot, chat_id: ChatId, lang: Lang, wait: Duration) -> Result<Message, Error> {
+    bot.send_message(chat_id, t!(lang, "pisun.cooldown", wait = format_wait(lang, wait)))
+        .await
+        .map_err(|e| e.into())
 }
 
-fn get_roll_message(change: i32) -> String {
-    let abs_change = change.abs();
-    
-    match change {
-        -10..=-7 => {
-            let messages = vec![
-                format!(
-                    "Ахахахах, неудачник. Твой огрызок стал меньше на целых {} см! 🍆📉", 
-                    abs_change
-                ),
-                format!(
-                    "Ахахахах, невдаха. Твій член став меншим на {} см! 🍆📉", 
-                    abs_change
-                )
-            ];
-                messages.choose(&mut rand::thread_rng()).unwrap().to_string()
-        },
-        -6..=-3 => {
-            let messages = vec![
-                format!(
-                    "Твой, и не без того маленький пенис, стал меньше аж на {} см. 😔🍆🤏", 
-                    abs_change
-                ),
-                format!(
-                    "Твій, і не без того маленький член, став меншим аж на {} см. 😔🍆🤏", 
-                    abs_change
-                )
-            ];
-            messages.choose(&mut rand::thread_rng()).unwrap().to_string()
-        },
-        -2..=-1 => {
-            let messages = vec![
-                format!(
-                    "Мои спутники зафиксировали уменьшение твоего полового органа на {} см. 😕🍆", 
-                    abs_change
-                ),
-                format!(
-                    "Мої супутники зафіксували зменшення твого статевого органу на {} см. 😕🍆", 
-                    abs_change
-                )
-            ];
-            messages.choose(&mut rand::thread_rng()).unwrap().to_string()
-        },
-        0 => {
-            let messages = vec![
-                "Нуууу, что тут можно ещё сказать... Твоя пипирка сегодня не выросла 🤔🍆".to_string(),
-                "Нууууу, що тут ще можна сказати... Твій член сьогодні не виріс 🤔🍆".to_string()
-            ];
-            messages.choose(&mut rand::thread_rng()).unwrap().to_string()
-        },
-        1..=3 => {
-            let messages = vec![
-                format!(
-                    "Отличный результат! Твой писюн увеличился на {} см. 🚀", 
-                    abs_change
-                ),
-                format!(
-                    "Вау! Твій член виріс на {} см. 🚀", 
-                    abs_change
-                )
-            ];
-            messages.choose(&mut rand::thread_rng()).unwrap().to_string()
-        },
-        4..=7 => {
-            let messages = vec![
-                format!(
-                    "Все тяночки вокруг в шоке! Твой гигантский половой орган стал больше на {} см. 🚀🍆", 
-                    abs_change
-                ),
-                format!(
-                    "Твій член став значно більше на {} см. 💪",
-                    abs_change
-                )
-            ];
-            messages.choose(&mut rand::thread_rng()).unwrap().to_string()
-        },
-        8..=9 => {
-            let messages = vec![
-                format!(
-                    "*Ах ты читер!* Каким-то образом ты смог увеличить свой писюн на {} см. 👑🍆🏆", 
-                    abs_change
-                ),
-                format!(
-                    "Новий рекорд! Твій член став {} см.",
-                    abs_change
-                )
-            ];
-            messages.choose(&mut rand::thread_rng()).unwrap().to_string()
-        },
-        10 => format!(
-            "🎉🎉🎉 Поздравляю! +{} см! Ты настоящий гигант! 💪🍆👑", 
-            abs_change
-        ),
-        _ => "Что-то пошло не так...".to_string(),
-    }
-}
 
-async fn meme_handler(bot: Bot, msg: Message) -> Result<(), Error> {
-    match get_random_meme().await {
-        Ok(meme_url) => {
-            let url = reqwest::Url::parse(&meme_url).expect("Неверный URL");
-            bot.send_photo(msg.chat.id, teloxide::types::InputFile::url(url)).await?;
-        }
-        Err(e) => {
-            eprintln!("Ошибка при получении мема: {}", e);
-            bot.send_message(msg.chat.id, "Не удалось получить мем").await?;
+async fn meme_handler(bot: Bot, msg: Message, http: &HttpClient, lang: Lang) -> Result<(), Error> {
+    match memes::pick(&memes::providers(lang), http).await {
+        Ok(meme) => {
+            let file = teloxide::types::InputFile::url(meme.url);
+            match meme.kind {
+                MemeKind::Photo => bot.send_photo(msg.chat.id, file).await?,
+                MemeKind::Animation => bot.send_animation(msg.chat.id, file).await?,
+                MemeKind::Video => bot.send_video(msg.chat.id, file).await?,
+            };
         }
+        Err(e) => report_source_failure(&bot, msg.chat.id, lang, Source::Memes, &e).await?,
     }
     Ok(())
 }
 
-async fn wisdom_handler(bot: Bot, msg: Message) -> Result<(), Error> {
-    let wisdoms = vec![
-        "Если жизнь даёт тебе лимоны, сделай лимонад. А если водку — зови друзей. 🍋🍹",
-        "Мудрость приходит с возрастом, но иногда возраст приходит один. 👴📜",
-        "Не важно, сколько у тебя проблем — важно, сколько у тебя мемов. 🤣📱",
-        "Деньги счастья не приносят, но с ними легче грустить в дорогой машине. 🚗💸",
-        "Если тебя не понимают — значит, ты говоришь слишком умно. 🧠🧐",
-        "Не откладывай на завтра то, что можно вообще не делать. 🛌✨",
-        "Лучше быть смешным, чем скучным. Даже если никто не смеётся. 🤡",
-        "Если упал — лежи. Земля — это тоже уютно. 🌍🛋️",
-        "ТЫ ЧО ТУПОЙ?"
-    ];
-
-    let wisdom = wisdoms.choose(&mut rand::thread_rng()).unwrap();
-    bot.send_message(msg.chat.id, wisdom.to_string()).await?;
+async fn wisdom_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let wisdom = config.lock().await.content(lang).random_wisdom();
+    bot.send_message(msg.chat.id, wisdom).await?;
+    Ok(())
+}
+
+async fn hangover_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let tip = config.lock().await.content(lang).random_hangover_tip();
+    bot.send_message(msg.chat.id, tip).await?;
     Ok(())
 }
 
-async fn hangover_handler(bot: Bot, msg: Message) -> Result<(), Error> {
-    let tips = vec![
-        "Вода, вода и еще раз вода! И никаких больше \"я только одну бутылочку\". 🍼🍺",
-        "Съешь что-нибудь жирное. Или хотя бы посмотри на фотографию еды. 🍔📸",
-        "Ибупрофен — твой новый лучший друг. Но не забудь про воду! 💊💧",
-        "Поспи чуть-чуть. Или не чуть-чуть. Главное — не просыпайся до понедельника. 🛏️💤",
-        "Контрастный душ. Или просто сиди в ванной и плачь. 🚿😢",
-        "Солёный огурец и рассол — твоё спасение! 🥒💚",
-        "Не забудь, что завтра ты снова скажешь \"больше не пью\". И это ложь. 🍷🚫",
-    ];
+async fn reload_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let user_id = msg.from.as_ref().map(|user| user.id.0 as i64).unwrap_or(0);
 
-    let tip = tips.choose(&mut rand::thread_rng()).unwrap();
-    bot.send_message(msg.chat.id, tip.to_string()).await?;
+    let message = {
+        let mut config = config.lock().await;
+        if !config.is_bot_admin(user_id) {
+            t!(lang, "reload.admins_only")
+        } else {
+            match config.reload_content() {
+                Ok(()) => {
+                    let content = config.content(lang);
+                    t!(
+                        lang,
+                        "reload.done",
+                        exercises = content.exercises.len(),
+                        wisdoms = content.wisdoms.len(),
+                        tips = content.hangover_tips.len(),
+                        rolls = content.rolls.len(),
+                    )
+                }
+                Err(e) => {
+                    log::warn!("Не удалось перечитать контент: {}", e);
+                    t!(lang, "reload.failed", error = e)
+                }
+            }
+        }
+    };
+
+    bot.send_message(msg.chat.id, message).await?;
     Ok(())
 }
 
-async fn random_movie_handler(bot: Bot, msg: Message) -> Result<(), Error> {
-    match get_random_movie().await {
-        Ok((text, poster_url))  => {
-            bot.send_photo(msg.chat.id, teloxide::types::InputFile::url(reqwest::Url::parse(&poster_url).expect("Invalid URL"))).caption(text).parse_mode(teloxide::types::ParseMode::MarkdownV2).await?;
-        },
-        Err(e) => {
-            eprintln!("Ошибка при получении фильма: {}", e);
-            bot.send_message(msg.chat.id, "Не удалось получить фильм").await?;
-        }   
-    }
+async fn random_movie_handler(bot: Bot, msg: Message, config: &SharedConfig, http: &HttpClient, lang: Lang, args: &str) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+
+    // Жанры нужны только для разбора фильтра, без аргументов их не запрашиваем
+    let genres = if args.trim().is_empty() {
+        Vec::new()
+    } else {
+        match movies::genres(http, lang).await {
+            Ok(genres) => genres,
+            Err(e) => return report_source_failure(&bot, msg.chat.id, lang, Source::Tmdb, &e).await,
+        }
+    };
+    let filter = match MovieFilter::parse(args, &genres) {
+        Ok(filter) => filter,
+        Err(FilterError::UnknownGenre(genre)) => {
+            let names = genres.iter().map(|genre| genre.name.as_str()).collect::<Vec<_>>().join(", ");
+            bot.send_message(msg.chat.id, t!(lang, "movie.unknown_genre", genre = genre, genres = names)).await?;
+            return Ok(());
+        }
+        Err(FilterError::BadNumber(value)) => {
+            bot.send_message(msg.chat.id, t!(lang, "movie.usage", value = value)).await?;
+            return Ok(());
+        }
+    };
+
+    let seen = config.lock().await.recently_seen(chat_id, SeenKind::Movie)?;
+    let movie = match movies::pick(http, lang, &filter, &seen).await {
+        Ok(movie) => movie,
+        Err(e) => return report_source_failure(&bot, msg.chat.id, lang, Source::Tmdb, &e).await,
+    };
+    let Some((movie, poster)) = movie.and_then(|movie| movie.poster_url().map(|poster| (movie, poster))) else {
+        bot.send_message(msg.chat.id, t!(lang, "movie.not_found")).await?;
+        return Ok(());
+    };
+
+    config.lock().await.mark_seen(chat_id, SeenKind::Movie, movie.id.to_string())?;
+    bot.send_photo(msg.chat.id, InputFile::url(poster))
+        .caption(movie_caption(lang, &movie))
+        .parse_mode(ParseMode::MarkdownV2)
+        .reply_markup(movie_keyboard(lang, &filter, movie.id))
+        .await?;
     Ok(())
 }
 
-async fn get_random_joke() -> Result<String, reqwest::Error> {
-    let client = Client::new();
-    let url = "https://baneks.ru/random";
-    let response = client.get(url).send().await?;
-    let body = response.text().await?;
+/// Кнопка «Другой фильм»: подбирает следующий по тому же фильтру и меняет постер с подписью в том же сообщении.
+async fn movie_callback(bot: &Bot, q: &CallbackQuery, lang: Lang, filter: &str, config: &SharedConfig, http: &HttpClient) -> Result<Option<String>, Error> {
+    let (Some(message), Some(filter)) = (&q.message, MovieFilter::from_callback(filter)) else {
+        return Ok(None);
+    };
+    let chat_id = message.chat().id;
+
+    let seen = config.lock().await.recently_seen(chat_id.0, SeenKind::Movie)?;
+    let movie = match movies::pick(http, lang, &filter, &seen).await {
+        Ok(movie) => movie,
+        Err(e) => return Ok(Some(source_failure_notice(lang, Source::Tmdb, &e))),
+    };
+    let Some((movie, poster)) = movie.and_then(|movie| movie.poster_url().map(|poster| (movie, poster))) else {
+        return Ok(Some(t!(lang, "movie.not_found")));
+    };
 
-    let document = Html::parse_document(&body);
-    let selector = Selector::parse("article p").unwrap();
+    config.lock().await.mark_seen(chat_id.0, SeenKind::Movie, movie.id.to_string())?;
+    let media = InputMediaPhoto::new(InputFile::url(poster))
+        .caption(movie_caption(lang, &movie))
+        .parse_mode(ParseMode::MarkdownV2);
+    bot.edit_message_media(chat_id, message.id(), InputMedia::Photo(media))
+        .reply_markup(movie_keyboard(lang, &filter, movie.id))
+        .await?;
+    Ok(None)
+}
+
+/// Кнопка «В список». Название берётся из TMDB на языке чата, а не из подписи к постеру.
+async fn watchlist_callback(q: &CallbackQuery, lang: Lang, movie_id: &str, config: &SharedConfig, http: &HttpClient) -> Result<Option<String>, Error> {
+    let (Some(message), Ok(movie_id)) = (&q.message, movie_id.parse()) else {
+        return Ok(None);
+    };
+    let movie = match movies::details(http, lang, movie_id).await {
+        Ok(movie) => movie,
+        Err(e) => return Ok(Some(source_failure_notice(lang, Source::Tmdb, &e))),
+    };
+    let added = config.lock().await.add_to_watchlist(message.chat().id.0, &movie, q.from.id.0 as i64)?;
+    Ok(Some(if added { t!(lang, "watchlist.added", title = movie.title) } else { t!(lang, "watchlist.already") }))
+}
 
-    if let Some(element) = document.select(&selector).next() {
-        let joke = element.text().collect::<Vec<_>>().join("\n");
-        Ok(joke)
+async fn watchlist_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang) -> Result<(), Error> {
+    let entries = config.lock().await.get_watchlist(msg.chat.id.0)?;
+    let text = if entries.is_empty() {
+        t!(lang, "watchlist.empty")
     } else {
-        Ok("Не удалось найти анекдот".to_string())
+        let lines: Vec<String> = entries.iter().enumerate().map(|(i, entry)| format!("{}. {}", i + 1, entry.title)).collect();
+        format!("{}\n{}", t!(lang, "watchlist.header", count = entries.len()), lines.join("\n"))
+    };
+    bot.send_message(msg.chat.id, text).await?;
+    Ok(())
+}
+
+/// Сколько фильмов в голосовании, если число не указано.
+const MOVIE_NIGHT_OPTIONS: usize = 5;
+
+/// Опрос между случайными фильмами из списка чата. Telegram закрывает его сам, итоги объявляет [`poll_handler`].
+async fn movie_night_handler(bot: Bot, msg: Message, config: &SharedConfig, lang: Lang, args: &str) -> Result<(), Error> {
+    let chat_id = msg.chat.id.0;
+    // В опросе Telegram от 2 до 10 вариантов
+    let count = args.trim().parse::<usize>().unwrap_or(MOVIE_NIGHT_OPTIONS).clamp(2, 10);
+    let (mut entries, period) = {
+        let mut config = config.lock().await;
+        (config.get_watchlist(chat_id)?, config.get_movie_night_period())
+    };
+    if entries.len() < 2 {
+        bot.send_message(msg.chat.id, t!(lang, "movienight.not_enough", count = entries.len())).await?;
+        return Ok(());
+    }
+
+    entries.shuffle(&mut rand::thread_rng());
+    entries.truncate(count);
+    // Вариант ответа — не длиннее 100 символов
+    let options: Vec<String> = entries.iter().map(|entry| entry.title.chars().take(100).collect()).collect();
+    let sent = bot.send_poll(msg.chat.id, t!(lang, "movienight.question"), options)
+        .open_period(period.as_secs() as u16)
+        .await?;
+
+    if let Some(poll) = sent.poll() {
+        config.lock().await.save_movie_poll(&MoviePoll {
+            poll_id: poll.id.clone(),
+            chat_id,
+            message_id: sent.id.0,
+            movie_ids: entries.iter().map(|entry| entry.movie_id).collect(),
+            started_at: Utc::now(),
+        })?;
+    }
+    Ok(())
+}
+
+/// Обновления опросов бота. Закрытый опрос /movienight объявляет победителя ответом на опрос
+/// и убирает фильм из списка; при ничьей победителя выбирает жребий.
+pub(crate) async fn poll_handler(bot: Bot, poll: Poll, config: SharedConfig) -> Result<(), Error> {
+    if !poll.is_closed {
+        return Ok(());
     }
+    let (movie_poll, lang) = {
+        let mut config = config.lock().await;
+        let Some(movie_poll) = config.take_movie_poll(&poll.id)? else {
+            return Ok(());
+        };
+        let lang = config.get_language(movie_poll.chat_id)?;
+        (movie_poll, lang)
+    };
+
+    let votes: Vec<u32> = poll.options.iter().map(|option| option.voter_count).collect();
+    let leaders = movies::poll_leaders(&votes);
+    let winner = leaders.choose(&mut rand::thread_rng()).copied();
+    let text = match winner {
+        None => t!(lang, "movienight.no_votes"),
+        Some(winner) => {
+            if let Some(&movie_id) = movie_poll.movie_ids.get(winner) {
+                config.lock().await.remove_from_watchlist(movie_poll.chat_id, movie_id)?;
+            }
+            let key = if leaders.len() > 1 { "movienight.tie" } else { "movienight.winner" };
+            t!(lang, key, title = poll.options[winner].text, votes = votes[winner])
+        }
+    };
+
+    bot.send_message(ChatId(movie_poll.chat_id), text)
+        .reply_parameters(ReplyParameters::new(MessageId(movie_poll.message_id)))
+        .await?;
+    Ok(())
 }
 
-fn get_weather_emoji(is_day: i64, weather_code: i64) -> String {
+// Подпись к постеру в MarkdownV2
+fn movie_caption(lang: Lang, movie: &Movie) -> String {
+    t!(lang, "movie.recommendation", title = escape(&movie.title), url = escape(&movie.tmdb_url()), overview = escape(&movie.overview))
+}
+
+fn movie_keyboard(lang: Lang, filter: &MovieFilter, movie_id: u64) -> InlineKeyboardMarkup {
+    InlineKeyboardMarkup::new(vec![
+        vec![InlineKeyboardButton::callback(t!(lang, "movie.another"), filter.to_callback())],
+        vec![InlineKeyboardButton::callback(t!(lang, "movie.watch"), format!("movie:watch:{}", movie_id))],
+    ])
+}
+
+fn get_weather_emoji(daytime: bool, weather_code: i64) -> String {
     match weather_code {
-        0 => if is_day == 1 { "☀️" } else { "🌙" },
-        1..=3 => if is_day == 1 { "🌤️" } else { "☁️" },
+        0 => if daytime { "☀️" } else { "🌙" },
+        1..=3 => if daytime { "🌤️" } else { "☁️" },
         45 | 48 => "🌫️",
         51..=55 | 61..=65 | 80..=82 => "🌧️",
         56..=57 | 66..=67 => "🌨️",
@@ -473,42 +1415,39 @@ fn get_weather_emoji(is_day: i64, weather_code: i64) -> String {
     }.to_string()
 }
 
-fn get_weather_description(code: i64) -> String {
-    match code {
-        0 => "Ясно",
-        1..=3 => "Переменная облачность",
-        45 | 48 => "Туман",
-        51..=55 => "Морось",
-        56..=57 => "Ледяная морось",
-        61..=65 => "Дождь",
-        66..=67 => "Ледяной дождь",
-        71..=75 => "Снег",
-        77 => "Снежные зерна",
-        80..=82 => "Ливневые дожди",
-        85..=86 => "Снежный ливень",
-        95 => "Гроза",
-        96..=99 => "Гроза с градом",
-        _ => "Неизвестные погодные условия",
-    }.to_string()
+fn get_weather_description(lang: Lang, code: i64) -> String {
+    let key = match code {
+        0 => "clear",
+        1..=3 => "partly_cloudy",
+        45 | 48 => "fog",
+        51..=55 => "drizzle",
+        56..=57 => "freezing_drizzle",
+        61..=65 => "rain",
+        66..=67 => "freezing_rain",
+        71..=75 => "snow",
+        77 => "snow_grains",
+        80..=82 => "showers",
+        85..=86 => "snow_showers",
+        95 => "thunderstorm",
+        96..=99 => "thunderstorm_hail",
+        _ => "unknown",
+    };
+    t!(lang, format!("weather.code.{}", key))
 }
 
-fn get_forecast(daily: &serde_json::Value, hourly: &serde_json::Value) -> String {
+fn get_forecast(lang: Lang, days: &[weather::Day]) -> String {
     let mut forecast = String::new();
-    for i in 1..3 {
-        let date = DateTime::from_timestamp(daily["time"][i].as_i64().unwrap(), 0).unwrap().with_timezone(&Local);
-        let max_temp = daily["temperature_2m_max"][i].as_f64().unwrap();
-        let min_temp = daily["temperature_2m_min"][i].as_f64().unwrap();
-        let weather_code = hourly["weathercode"][i * 24].as_i64().unwrap();
-        let weather_emoji = get_weather_emoji( 1, weather_code);
-        
-        forecast.push_str(&format!(
-            "{} {}: от {:.1}°C до {:.1}°C, {}\n",
-            date.format("%d.%m").to_string(),
-            weather_emoji,
-            min_temp,
-            max_temp,
-            get_weather_description(weather_code)
+    for day in days {
+        forecast.push_str(&t!(
+            lang,
+            "weather.forecast_row",
+            date = day.date.format("%d.%m"),
+            emoji = get_weather_emoji(true, day.code),
+            min = format!("{:.1}", day.min),
+            max = format!("{:.1}", day.max),
+            description = get_weather_description(lang, day.code),
         ));
+        forecast.push('\n');
     }
     forecast
 }
@@ -530,83 +1469,71 @@ fn calculate_pisun_change(temperature: f64) -> i32 {
     }
 }
 
-fn generate_training_exercise() -> (TrainingExercise, bool) {
-    let exercises = get_training_exercises();
-    let exercise = exercises.choose(&mut rand::thread_rng()).unwrap().clone();
+fn get_source_emoji(source: ChangeSource) -> &'static str {
+    match source {
+        ChangeSource::Roll => "🎲",
+        ChangeSource::Training => "🏋️",
+        ChangeSource::Weather => "🌦️",
+        ChangeSource::Duel => "⚔️",
+        ChangeSource::Season => "🗓️",
+    }
+}
+
+fn generate_training_exercise(content: &ContentPack) -> (TrainingExercise, bool) {
+    let exercise = content.random_exercise();
     let success = rand::thread_rng().gen_bool(exercise.success_rate);
 
     (exercise, success)
 }
 
-fn process_training_result(success: bool, current_size: i32) -> (i32, String) {
+fn process_training_result(lang: Lang, success: bool, current_size: i32) -> (i32, String) {
     let mut rng = rand::thread_rng();
 
     if success {
         let change = rng.gen_range(1..=3);
-        (change, format!("Успех! Твой писюн вырос на {} см. 🎉", change))
+        (change, t!(lang, "train.success", cm = change))
     } else {
-        let change = if current_size > 5 {
+        let change: i32 = if current_size > 5 {
             -rng.gen_range(1..=2)
         } else {
             0
         };
-        (change, format!("Неудача! {} 😔", if change < 0 { format!("Твой писюн уменьшился на {} см.", change.abs()) } else { "Но твой писюн не пострадал.".to_string() }))
+        let message = if change < 0 { t!(lang, "train.shrunk", cm = change.abs()) } else { t!(lang, "train.unharmed") };
+        (change, message)
     }
 }
 
-async fn get_random_meme() -> Result<String, String> {
-    let client = reqwest::Client::new();
-    let url = "https://pda.anekdot.ru/random/mem/";
-    let response = client.get(url).send().await.map_err(|e | format!("Ошибка при получении мема: {}", e))?;
-    
-    if response.status().is_success() {
-        let body = response.text().await.map_err(|e | format!("Ошибка при получении мема: {}", e))?;
-
-        let document = scraper::Html::parse_document(&body);
-        let selector = scraper::Selector::parse(".content img").unwrap();
-
-        let image_element = document.select(&selector).next();
-        if let Some(element) = image_element {
-            let image_url = element.value().attr("src").unwrap_or("");
-            Ok(image_url.to_string())
-        } else {
-            Ok("Не удалось найти изображение".to_string())
+// Соперник задаётся через @username или ответом на его сообщение, ставка — первым числом среди аргументов
+fn parse_duel_args(args: &str) -> (Option<String>, Option<i32>) {
+    let mut username = None;
+    let mut stake = None;
+    for arg in args.split_whitespace() {
+        match arg.strip_prefix('@') {
+            Some(name) => username = Some(name.to_lowercase()),
+            None => stake = stake.or(arg.parse::<i32>().ok()),
         }
-    } else {
-        Err(format!("Ошибка при получении мема: {}", response.status()))
     }
+    (username, stake)
 }
 
-async fn get_random_movie() -> Result<(String, String), String> {
-    let api_key = std::env::var("TMDB_API_KEY").map_err(|e| format!("TMDB_API_KEY не установлен: {}", e))?;
-    let url = format!("https://api.themoviedb.org/3/movie/top_rated?api_key={}&language=ru-RU&page=1", api_key);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let client = reqwest::Client::new();
-    let response = client.get(&url).send().await.map_err(|e| format!("Ошибка при запросе к TMDB: {}", e))?;
-
-    if response.status().is_success() {
-        let body = response.text().await.map_err(|e| format!("Ошибка при чтении ответа от TMDB: {}", e))?;
-        let json: serde_json::Value = serde_json::from_str(&body).map_err(|e| format!("Ошибка при парсинге JSON: {}", e))?;
-
-        let results = json["results"].as_array().ok_or("В ответе TMDB нет поля 'results'")?;
-        let movie = results.choose(&mut rand::thread_rng()).ok_or("Список фильмов пуст")?;
-
-        let title = movie["title"].as_str().ok_or("В фильме нет поля 'title'")?.to_string();
-        let overview = movie["overview"].as_str().ok_or("В фильме нет поля 'overview'")?.to_string();
-        let poster_path = movie["poster_path"].as_str().ok_or("В фильме нет поля 'poster_path'")?.to_string();
-        let movie_id = movie["id"].as_u64().ok_or("В фильме нет поля 'id'")?;
-
-        let poster_url = format!("https://image.tmdb.org/t/p/w500{}", poster_path);
-        let tmdb_url = format!("https://www.themoviedb.org/movie/{}", movie_id);
-
-        let escaped_title = escape(&title);
-        let escaped_overview = escape(&overview);
-        let escaped_tmdb_url = escape(&tmdb_url);
-
-        let text = format!("🎥 Сегодня рекомендуем посмотреть: *{}* \\({}\\)\n\n{}", escaped_title, escaped_tmdb_url, escaped_overview);
+    #[test]
+    fn duel_stake_is_the_first_number() {
+        assert_eq!(parse_duel_args("5 @Bob"), (Some("bob".to_string()), Some(5)));
+        assert_eq!(parse_duel_args("@bob 5 please 7"), (Some("bob".to_string()), Some(5)));
+        assert_eq!(parse_duel_args("@bob -3"), (Some("bob".to_string()), Some(-3)));
+        assert_eq!(parse_duel_args("@bob all"), (Some("bob".to_string()), None));
+    }
 
-        Ok((text, poster_url))
-    } else {
-        Err(format!("Ошибка при запросе к TMDB: код {}", response.status()))
+    #[test]
+    fn every_command_has_translated_description() {
+        for lang in Lang::ALL {
+            for command in localized_commands(lang) {
+                assert!(!command.description.starts_with("command."), "{}: нет описания {}", lang.code(), command.command);
+            }
+        }
     }
-}
\ No newline at end of file
+}
@@ -1,62 +1,424 @@
-use std::collections::HashMap;
-use std::fs;
+use std::sync::Arc;
 use teloxide::Bot;
-use crate::models::UserData;
+use teloxide::types::User;
+use tokio::sync::Mutex;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use std::collections::{HashMap, HashSet};
+use crate::content::{ContentError, ContentPack};
+use crate::cooldown::DailyReset;
+use crate::duel::{DuelBoard, DuelSettings};
+use crate::http::{HttpClient, Policy, Source};
+use crate::i18n::Lang;
+use crate::jokes::{AnekdotRu, Baneks, JokeProvider, Offline, DEFAULT_WEIGHTS};
+use crate::movies::Movie;
+use crate::season::{Season, SeasonLength};
+use crate::models::{
+    check_achievements, Achievement, AchievementCheck, ChangeSource, ChatSettings, Location, MoviePoll, SeasonResult, SeenItem, SeenKind, SeasonStanding,
+    SizeEvent, UnlockedAchievement, UserData, WatchlistEntry,
+};
+use crate::error::Error;
+use crate::storage::{self, Backend, BackupPolicy, Storage, StorageError};
+
+/// Общая конфигурация, создаётся один раз в `loader::run` и разделяется между обработчиками.
+pub type SharedConfig = Arc<Mutex<Config>>;
 
 pub struct Config {
     bot: Bot,
-    file_path: String,
-    users: HashMap<i64, UserData>,
+    storage: Box<dyn Storage>,
+    /// Сброс дневных лимитов для чатов без своих настроек
+    default_reset: DailyReset,
+    /// Язык ответов в чатах, где его не выбирали
+    default_language: Lang,
+    duel_settings: DuelSettings,
+    season_length: SeasonLength,
+    content_dir: std::path::PathBuf,
+    content: HashMap<Lang, ContentPack>,
+    /// Пользователи, которым доступны команды обслуживания бота (ADMIN_IDS)
+    admins: Vec<i64>,
+    /// Вызовы на дуэль, ждущие ответа
+    duels: DuelBoard,
+    http: HttpClient,
+    /// Веса источников анекдотов по имени
+    joke_weights: HashMap<String, u32>,
+    /// Сколько не повторять анекдот в чате
+    joke_repeat_window: chrono::Duration,
+    /// Сколько не предлагать фильм в чате повторно
+    movie_repeat_window: chrono::Duration,
+    /// Сколько длится голосование /movienight; Telegram разрешает не больше 10 минут
+    movie_night_period: std::time::Duration,
 }
 
 impl Config {
-    pub async fn new() -> Self {
-        let bot_token = std::env::var("TELOXIDE_TOKEN").expect("BOT_TOKEN must be set");
+    /// Загружает конфигурацию из окружения, открывает хранилище и читает контент.
+    /// Ошибка означает, что данные повреждены и восстановить их из резервных копий не удалось,
+    /// либо что файлы контента не прошли проверку.
+    pub async fn new() -> Result<Self, Error> {
+        let bot_token = std::env::var("TELOXIDE_TOKEN").map_err(|_| Error::Config("TELOXIDE_TOKEN must be set".to_string()))?;
         let bot = Bot::new(bot_token);
-        let file_path = "users.json".to_string();
-        
-        // Загружаем пользователей при создании конфига
-        let users = match fs::read_to_string(&file_path) {
-            Ok(data) => serde_json::from_str(&data).unwrap_or_default(),
-            Err(_) => HashMap::new(),
-        };
-        
-        Config { 
+
+        // Тип хранилища и путь к нему задаются через STORAGE_BACKEND и STORAGE_PATH
+        let backend = env_setting("STORAGE_BACKEND", Backend::from_name, "json or sqlite")?.unwrap_or(Backend::Json);
+        let path = std::env::var("STORAGE_PATH").unwrap_or_else(|_| backend.default_path().to_string());
+        let storage = storage::open(backend, &path, backup_policy())?;
+
+        // Часовой пояс и час сброса лимитов по умолчанию: DEFAULT_TIMEZONE и DEFAULT_RESET_HOUR
+        let timezone = env_setting("DEFAULT_TIMEZONE", |name| name.parse().ok(), "an IANA timezone, e.g. Asia/Omsk")?
+            .unwrap_or(chrono_tz::Asia::Omsk);
+        let default_reset = DailyReset::new(timezone, env_number("DEFAULT_RESET_HOUR").unwrap_or(0));
+
+        // Язык ответов по умолчанию: DEFAULT_LANGUAGE=ru, uk или en
+        let default_language = env_setting("DEFAULT_LANGUAGE", Lang::from_code, "ru, uk or en")?.unwrap_or(Lang::Ru);
+
+        // Длина сезона: SEASON_LENGTH=month, week или число дней
+        let season_length = env_setting("SEASON_LENGTH", SeasonLength::from_name, "month, week or a number of days")?
+            .unwrap_or(SeasonLength::Month);
+
+        // Тексты бота лежат в CONTENT_DIR/<язык>, по умолчанию в content/
+        let content_dir = std::path::PathBuf::from(std::env::var("CONTENT_DIR").unwrap_or_else(|_| "content".to_string()));
+        let content = ContentPack::load_all(&content_dir)?;
+        let admins = std::env::var("ADMIN_IDS")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|id| id.trim().parse().ok())
+            .collect();
+
+        Ok(Config {
             bot,
-            file_path,
-            users,
-        }
+            storage,
+            default_reset,
+            default_language,
+            duel_settings: duel_settings(),
+            season_length,
+            content_dir,
+            content,
+            admins,
+            duels: DuelBoard::default(),
+            http: HttpClient::new(http_policies()),
+            joke_weights: joke_weights(),
+            joke_repeat_window: chrono::Duration::days(env_number("JOKE_REPEAT_DAYS").unwrap_or(30)),
+            movie_repeat_window: chrono::Duration::days(env_number("MOVIE_REPEAT_DAYS").unwrap_or(180)),
+            movie_night_period: std::time::Duration::from_secs(env_number::<u64>("MOVIE_NIGHT_MINUTES").unwrap_or(10).clamp(1, 10) * 60),
+        })
     }
 
     pub fn get_bot(&self) -> &Bot { &self.bot }
 
-    pub fn get_or_create_user(&mut self, user_id: i64) -> &mut UserData {
-        if !self.users.contains_key(&user_id) {
-            self.users.insert(user_id, UserData::new(user_id));
-            self.save_users();
+    pub fn get_http(&self) -> &HttpClient { &self.http }
+
+    pub fn get_default_language(&self) -> Lang { self.default_language }
+
+    /// Тексты на нужном языке. Наборы всех языков загружаются вместе, так что нужный есть всегда.
+    pub fn content(&self, lang: Lang) -> &ContentPack { &self.content[&lang] }
+
+    /// Перечитывает контент с диска. Если новые файлы не прошли проверку, остаётся старый контент.
+    pub fn reload_content(&mut self) -> Result<(), ContentError> {
+        self.content = ContentPack::load_all(&self.content_dir)?;
+        Ok(())
+    }
+
+    /// Источники анекдотов с весами; запасные анекдоты берутся на языке чата.
+    pub fn joke_providers(&self, lang: Lang) -> Vec<(Box<dyn JokeProvider>, u32)> {
+        let providers: [Box<dyn JokeProvider>; 3] = [
+            Box::new(Baneks),
+            Box::new(AnekdotRu),
+            Box::new(Offline(self.content(lang).jokes.clone())),
+        ];
+        providers
+            .into_iter()
+            .map(|provider| {
+                let weight = self.joke_weights.get(provider.name()).copied().unwrap_or(0);
+                (provider, weight)
+            })
+            .collect()
+    }
+
+    /// Отпечатки того, что уже показывали в чате и пока не стоит повторять.
+    pub fn recently_seen(&mut self, chat_id: i64, kind: SeenKind) -> Result<HashSet<String>, StorageError> {
+        let since = Utc::now() - self.repeat_window(kind);
+        Ok(self.storage.seen_since(chat_id, kind, since)?.into_iter().collect())
+    }
+
+    pub fn mark_seen(&mut self, chat_id: i64, kind: SeenKind, key: String) -> Result<(), StorageError> {
+        let at = Utc::now();
+        let forget_before = at - self.repeat_window(kind);
+        self.storage.mark_seen(&SeenItem { chat_id, kind, key, at }, forget_before)
+    }
+
+    fn repeat_window(&self, kind: SeenKind) -> chrono::Duration {
+        match kind {
+            SeenKind::Joke => self.joke_repeat_window,
+            SeenKind::Movie => self.movie_repeat_window,
         }
-        self.users.get_mut(&user_id).unwrap()
     }
 
-    // pub fn get_user(&self, user_id: i64) -> Option<&UserData> {
-    //     self.users.get(&user_id)
-    // }
+    pub fn is_bot_admin(&self, user_id: i64) -> bool {
+        self.admins.contains(&user_id)
+    }
+
+    pub fn get_duel_settings(&self) -> DuelSettings { self.duel_settings }
 
-    pub fn update_user(&mut self, user_id: i64, update_fn: impl FnOnce(&mut UserData)) {
-        if let Some(user) = self.users.get_mut(&user_id) {
-            update_fn(user);
-            self.save_users();
+    pub fn duels(&mut self) -> &mut DuelBoard { &mut self.duels }
+
+    pub fn get_or_create_user(&mut self, chat_id: i64, user_id: i64) -> Result<UserData, StorageError> {
+        match self.storage.get_user(chat_id, user_id)? {
+            Some(user) => Ok(user),
+            None => {
+                let user = UserData::new(chat_id, user_id);
+                self.storage.save_user(&user)?;
+                Ok(user)
+            }
+        }
+    }
+
+    /// Обновляет сохранённое имя пользователя, если оно поменялось в Telegram.
+    /// Новых записей не создаёт: в рейтинг попадают только те, кто мерился.
+    pub fn update_profile(&mut self, chat_id: i64, from: &User) -> Result<(), StorageError> {
+        if let Some(mut user) = self.storage.get_user(chat_id, from.id.0 as i64)? {
+            if user.set_profile(from) {
+                self.storage.save_user(&user)?;
+            }
         }
+        Ok(())
+    }
+
+    // Получить всех пользователей чата (для команд top и size)
+    pub fn get_chat_users(&mut self, chat_id: i64) -> Result<Vec<UserData>, StorageError> {
+        self.storage.chat_users(chat_id)
+    }
+
+    /// Сохраняет пользователей вместе с событиями истории одной операцией,
+    /// чтобы история и размер не разошлись при ошибке записи.
+    pub fn save_batch(&mut self, users: &[UserData], events: &[SizeEvent]) -> Result<(), StorageError> {
+        self.storage.save_batch(users, events)
+    }
+
+    // История изменений размера (для команды history), от старых к новым
+    pub fn get_user_events(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<SizeEvent>, StorageError> {
+        self.storage.user_events(chat_id, user_id)
+    }
+
+    pub fn get_chat_events(&mut self, chat_id: i64) -> Result<Vec<SizeEvent>, StorageError> {
+        self.storage.chat_events(chat_id)
     }
 
-    // Получить всех пользователей (для команды top)
-    pub fn get_all_users(&self) -> &HashMap<i64, UserData> {
-        &self.users
+    pub fn get_user_achievements(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<UnlockedAchievement>, StorageError> {
+        self.storage.user_achievements(chat_id, user_id)
     }
 
-    fn save_users(&self) {
-        if let Ok(data) = serde_json::to_string_pretty(&self.users) {
-            let _ = fs::write(&self.file_path, data);
+    /// Проверяет достижения после изменения размера и сохраняет новые.
+    /// `change` — изменение до ограничения нулём, `roll` — выпавшее на замере число до штрафа за серию.
+    /// Возвращает только что открытые достижения.
+    pub fn unlock_achievements(&mut self, event: &SizeEvent, change: i32, roll: Option<i32>) -> Result<Vec<Achievement>, StorageError> {
+        let (chat_id, user_id) = (event.chat_id, event.user_id);
+        let unlocked = self.storage.user_achievements(chat_id, user_id)?;
+
+        let mut users = self.storage.chat_users(chat_id)?;
+        users.sort_by_key(|u| (std::cmp::Reverse(u.pisun), u.user_id));
+        let position = users.iter().position(|u| u.user_id == user_id);
+
+        let check = AchievementCheck {
+            source: event.source,
+            before: event.size - event.delta,
+            change,
+            roll,
+            after: event.size,
+            training_streak: position.map_or(0, |i| users[i].train_streak.current),
+            rank: position.map_or(0, |i| i + 1),
+            chat_size: users.len(),
+        };
+        let new: Vec<_> = check_achievements(&check)
+            .into_iter()
+            .filter(|achievement| !unlocked.iter().any(|u| u.achievement == *achievement))
+            .collect();
+        for achievement in &new {
+            self.storage.save_achievement(&UnlockedAchievement { chat_id, user_id, achievement: *achievement, at: event.at })?;
         }
+        Ok(new)
+    }
+
+    pub fn update_chat_settings(&mut self, chat_id: i64, update_fn: impl FnOnce(&mut ChatSettings)) -> Result<(), StorageError> {
+        let mut settings = self.storage.chat_settings(chat_id)?;
+        update_fn(&mut settings);
+        self.storage.save_chat_settings(&settings)
     }
-}
\ No newline at end of file
+
+    /// Текущий сезон чата и сброс дневных лимитов, по которому считаются его границы.
+    pub fn get_season(&mut self, chat_id: i64, now: DateTime<Utc>) -> Result<(Season, DailyReset), StorageError> {
+        let reset = self.get_daily_reset(chat_id)?;
+        Ok((self.season_length.season_of(reset.game_day(now)), reset))
+    }
+
+    pub fn get_chat_seasons(&mut self, chat_id: i64) -> Result<Vec<SeasonResult>, StorageError> {
+        self.storage.chat_seasons(chat_id)
+    }
+
+    pub fn get_watchlist(&mut self, chat_id: i64) -> Result<Vec<WatchlistEntry>, StorageError> {
+        self.storage.watchlist(chat_id)
+    }
+
+    /// Добавляет фильм в список чата; false — он там уже был.
+    pub fn add_to_watchlist(&mut self, chat_id: i64, movie: &Movie, added_by: i64) -> Result<bool, StorageError> {
+        self.storage.add_to_watchlist(&WatchlistEntry {
+            chat_id,
+            movie_id: movie.id,
+            title: movie.title.clone(),
+            added_by,
+            added_at: Utc::now(),
+        })
+    }
+
+    pub fn remove_from_watchlist(&mut self, chat_id: i64, movie_id: u64) -> Result<(), StorageError> {
+        self.storage.remove_from_watchlist(chat_id, movie_id)
+    }
+
+    pub fn get_movie_night_period(&self) -> std::time::Duration { self.movie_night_period }
+
+    pub fn save_movie_poll(&mut self, poll: &MoviePoll) -> Result<(), StorageError> {
+        self.storage.save_movie_poll(poll)
+    }
+
+    pub fn take_movie_poll(&mut self, poll_id: &str) -> Result<Option<MoviePoll>, StorageError> {
+        self.storage.take_movie_poll(poll_id)
+    }
+
+    /// Завершает сезон, если он закончился: сохраняет итоговую таблицу в архив и обнуляет размеры.
+    /// Возвращает итоги, если в сезоне было кого награждать.
+    pub fn finish_season(&mut self, chat_id: i64, now: DateTime<Utc>) -> Result<Option<SeasonResult>, StorageError> {
+        let (current, _) = self.get_season(chat_id, now)?;
+        let mut settings = self.storage.chat_settings(chat_id)?;
+        let finished = match settings.season_start {
+            Some(start) if start == current.start => return Ok(None),
+            Some(start) => Season { start, end: current.start },
+            // Чат впервые застал сезоны: просто запоминаем начало текущего
+            None => {
+                settings.season_start = Some(current.start);
+                self.storage.save_chat_settings(&settings)?;
+                return Ok(None);
+            }
+        };
+
+        let mut users = self.storage.chat_users(chat_id)?;
+        users.retain(|u| u.pisun > 0);
+        users.sort_by_key(|u| (std::cmp::Reverse(u.pisun), u.user_id));
+        let result = SeasonResult {
+            chat_id,
+            start: finished.start,
+            end: finished.end,
+            standings: users.iter()
+                .map(|u| SeasonStanding { user_id: u.user_id, name: u.display_name(), pisun: u.pisun })
+                .collect(),
+        };
+
+        // Архив пишется первым: если обнуление не сохранится, повторная запись заменит тот же сезон
+        if !result.standings.is_empty() {
+            self.storage.save_season(&result)?;
+        }
+        let events: Vec<_> = users.iter()
+            .map(|u| SizeEvent::new(chat_id, u.user_id, ChangeSource::Season, u.pisun, 0))
+            .collect();
+        for user in &mut users {
+            user.pisun = 0;
+        }
+        self.storage.save_batch(&users, &events)?;
+        settings.season_start = Some(current.start);
+        self.storage.save_chat_settings(&settings)?;
+
+        Ok(Some(result).filter(|r| !r.standings.is_empty()))
+    }
+
+    /// Язык ответов в чате: выбранный через /language или язык по умолчанию.
+    pub fn get_language(&mut self, chat_id: i64) -> Result<Lang, StorageError> {
+        let settings = self.storage.chat_settings(chat_id)?;
+        Ok(settings.language
+            .and_then(|code| Lang::from_code(&code))
+            .unwrap_or(self.default_language))
+    }
+
+    /// Город для /weather, если его выбрали через /setcity.
+    pub fn get_location(&mut self, chat_id: i64) -> Result<Option<Location>, StorageError> {
+        Ok(self.storage.chat_settings(chat_id)?.location)
+    }
+
+    /// Когда в чате обновляются дневные лимиты: настройки чата поверх значений по умолчанию.
+    pub fn get_daily_reset(&mut self, chat_id: i64) -> Result<DailyReset, StorageError> {
+        let settings = self.storage.chat_settings(chat_id)?;
+        let timezone = settings.timezone
+            .and_then(|name| name.parse::<Tz>().ok())
+            .unwrap_or(self.default_reset.timezone);
+        let reset_hour = settings.reset_hour.unwrap_or(self.default_reset.reset_hour);
+        Ok(DailyReset::new(timezone, reset_hour))
+    }
+}
+
+// Резервные копии настраиваются через BACKUP_DIR, BACKUP_COUNT и BACKUP_INTERVAL_MINUTES
+fn backup_policy() -> BackupPolicy {
+    let default = BackupPolicy::default();
+    BackupPolicy {
+        dir: std::env::var("BACKUP_DIR").map(Into::into).unwrap_or(default.dir),
+        keep: env_number("BACKUP_COUNT").unwrap_or(default.keep),
+        interval: env_number::<u64>("BACKUP_INTERVAL_MINUTES")
+            .map(|minutes| std::time::Duration::from_secs(minutes * 60))
+            .unwrap_or(default.interval),
+    }
+}
+
+// Политика каждого источника переопределяется через <ИСТОЧНИК>_TIMEOUT_SECONDS, <ИСТОЧНИК>_RETRIES,
+// <ИСТОЧНИК>_BREAKER_FAILURES и <ИСТОЧНИК>_BREAKER_MINUTES, например JOKES_TIMEOUT_SECONDS=15
+fn http_policies() -> HashMap<Source, Policy> {
+    Source::ALL
+        .into_iter()
+        .map(|source| {
+            let default = Policy::for_source(source);
+            let var = |name: &str| format!("{}_{}", source.key().to_uppercase(), name);
+            let policy = Policy {
+                timeout: env_number(&var("TIMEOUT_SECONDS")).map(std::time::Duration::from_secs).unwrap_or(default.timeout),
+                retries: env_number(&var("RETRIES")).unwrap_or(default.retries).min(Policy::MAX_RETRIES),
+                breaker_failures: env_number(&var("BREAKER_FAILURES")).unwrap_or(default.breaker_failures),
+                breaker_cooldown: env_number::<u64>(&var("BREAKER_MINUTES"))
+                    .map(|minutes| std::time::Duration::from_secs(minutes * 60))
+                    .unwrap_or(default.breaker_cooldown),
+                ..default
+            };
+            (source, policy)
+        })
+        .collect()
+}
+
+// Веса источников анекдотов: JOKE_WEIGHTS=baneks:3,anekdot.ru:2,offline:1; вес 0 выключает источник
+fn joke_weights() -> HashMap<String, u32> {
+    let mut weights: HashMap<String, u32> = DEFAULT_WEIGHTS.iter().map(|(name, weight)| (name.to_string(), *weight)).collect();
+    let overrides = std::env::var("JOKE_WEIGHTS").unwrap_or_default();
+    for entry in overrides.split(',').filter(|entry| !entry.trim().is_empty()) {
+        match entry.split_once(':').map(|(name, weight)| (name.trim(), weight.trim().parse())) {
+            Some((name, Ok(weight))) if weights.contains_key(name) => {
+                weights.insert(name.to_string(), weight);
+            }
+            _ => log::warn!("JOKE_WEIGHTS: не понял «{}», ожидается имя:вес", entry),
+        }
+    }
+    weights
+}
+
+// Откат и время ожидания ответа на дуэль: DUEL_COOLDOWN_MINUTES и DUEL_TIMEOUT_SECONDS
+fn duel_settings() -> DuelSettings {
+    let default = DuelSettings::default();
+    DuelSettings {
+        cooldown: env_number("DUEL_COOLDOWN_MINUTES").map(chrono::Duration::minutes).unwrap_or(default.cooldown),
+        timeout: env_number("DUEL_TIMEOUT_SECONDS").map(chrono::Duration::seconds).unwrap_or(default.timeout),
+    }
+}
+
+fn env_number<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok()?.parse().ok()
+}
+
+// Необязательная настройка: None, если переменная не задана, и ошибка, если задана неверно
+fn env_setting<T>(name: &str, parse: impl FnOnce(&str) -> Option<T>, expected: &str) -> Result<Option<T>, Error> {
+    match std::env::var(name) {
+        Ok(value) => parse(&value)
+            .map(Some)
+            .ok_or_else(|| Error::Config(format!("{} must be {}, got \"{}\"", name, expected, value))),
+        Err(_) => Ok(None),
+    }
+}
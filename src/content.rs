@@ -0,0 +1,265 @@
+//! Тексты бота, которые можно менять без пересборки: упражнения, мудрости,
+//! советы с похмелья, сообщения замера и запасные анекдоты. Загружаются из TOML-файлов в
+//! `CONTENT_DIR/<код языка>/` при запуске и по команде /reload.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use rand::seq::SliceRandom;
+use serde::Deserialize;
+use crate::i18n::Lang;
+use crate::models::TrainingExercise;
+
+/// Изменения размера при замере, для каждого из которых должно быть сообщение.
+pub const ROLL_RANGE: std::ops::RangeInclusive<i32> = -10..=10;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct RollBucket {
+    pub min: i32,
+    pub max: i32,
+    /// `{cm}` заменяется на изменение без знака
+    pub messages: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ContentPack {
+    pub exercises: Vec<TrainingExercise>,
+    pub wisdoms: Vec<String>,
+    pub hangover_tips: Vec<String>,
+    pub rolls: Vec<RollBucket>,
+    /// Анекдоты без сети, когда сайты не отвечают или всё уже показано
+    pub jokes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ExercisesFile {
+    exercise: Vec<TrainingExercise>,
+}
+
+#[derive(Deserialize)]
+struct WisdomsFile {
+    wisdoms: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct HangoverFile {
+    tips: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct JokesFile {
+    jokes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct RollsFile {
+    bucket: Vec<RollBucket>,
+}
+
+impl ContentPack {
+    /// Читает наборы текстов всех языков. Набор нужен для каждого языка, иначе часть чатов
+    /// осталась бы без ответов.
+    pub fn load_all(dir: &Path) -> Result<HashMap<Lang, Self>, ContentError> {
+        Lang::ALL
+            .into_iter()
+            .map(|lang| match Self::load(&dir.join(lang.code())) {
+                Ok(pack) => Ok((lang, pack)),
+                Err(ContentError::Invalid(problems)) => Err(ContentError::Invalid(
+                    problems.into_iter().map(|p| format!("{}/{}", lang.code(), p)).collect(),
+                )),
+                Err(e) => Err(e),
+            })
+            .collect()
+    }
+
+    /// Читает и проверяет все файлы каталога. Ошибки проверки собираются целиком,
+    /// чтобы их можно было исправить за один раз.
+    pub fn load(dir: &Path) -> Result<Self, ContentError> {
+        let pack = Self {
+            exercises: read::<ExercisesFile>(dir, "exercises.toml")?.exercise,
+            wisdoms: read::<WisdomsFile>(dir, "wisdoms.toml")?.wisdoms,
+            hangover_tips: read::<HangoverFile>(dir, "hangover.toml")?.tips,
+            rolls: read::<RollsFile>(dir, "rolls.toml")?.bucket,
+            jokes: read::<JokesFile>(dir, "jokes.toml")?.jokes,
+        };
+
+        let problems = pack.validate();
+        if !problems.is_empty() {
+            return Err(ContentError::Invalid(problems));
+        }
+        Ok(pack)
+    }
+
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        if self.exercises.is_empty() {
+            problems.push("exercises.toml: нет ни одного упражнения".to_string());
+        }
+        for (i, exercise) in self.exercises.iter().enumerate() {
+            if exercise.description.trim().is_empty() {
+                problems.push(format!("exercises.toml: у упражнения №{} пустое описание", i + 1));
+            }
+            if !(0.0..=1.0).contains(&exercise.success_rate) {
+                problems.push(format!(
+                    "exercises.toml: success_rate упражнения №{} должен быть от 0 до 1, а не {}",
+                    i + 1, exercise.success_rate
+                ));
+            }
+        }
+
+        check_texts(&mut problems, "wisdoms.toml", "мудрости", &self.wisdoms);
+        check_texts(&mut problems, "hangover.toml", "совета", &self.hangover_tips);
+        check_texts(&mut problems, "jokes.toml", "анекдота", &self.jokes);
+
+        for bucket in &self.rolls {
+            if bucket.min > bucket.max {
+                problems.push(format!("rolls.toml: в группе [{}, {}] min больше max", bucket.min, bucket.max));
+            }
+            if bucket.messages.is_empty() {
+                problems.push(format!("rolls.toml: в группе [{}, {}] нет сообщений", bucket.min, bucket.max));
+            }
+            for (i, message) in bucket.messages.iter().enumerate() {
+                if message.trim().is_empty() {
+                    problems.push(format!("rolls.toml: в группе [{}, {}] пустое сообщение №{}", bucket.min, bucket.max, i + 1));
+                }
+            }
+        }
+        let uncovered: Vec<String> = ROLL_RANGE
+            .filter(|change| self.roll_messages(*change).next().is_none())
+            .map(|change| change.to_string())
+            .collect();
+        if !uncovered.is_empty() {
+            problems.push(format!("rolls.toml: нет сообщений для изменений {}", uncovered.join(", ")));
+        }
+
+        problems
+    }
+
+    fn roll_messages(&self, change: i32) -> impl Iterator<Item = &String> {
+        self.rolls
+            .iter()
+            .filter(move |bucket| (bucket.min..=bucket.max).contains(&change))
+            .flat_map(|bucket| &bucket.messages)
+    }
+
+    /// Случайное сообщение для результата замера.
+    pub fn roll_message(&self, change: i32) -> String {
+        let messages: Vec<_> = self.roll_messages(change).collect();
+        match messages.choose(&mut rand::thread_rng()) {
+            Some(message) => message.replace("{cm}", &change.abs().to_string()),
+            None => "Что-то пошло не так...".to_string(),
+        }
+    }
+
+    pub fn random_exercise(&self) -> TrainingExercise {
+        self.exercises.choose(&mut rand::thread_rng()).cloned().unwrap()
+    }
+
+    pub fn random_wisdom(&self) -> String {
+        self.wisdoms.choose(&mut rand::thread_rng()).cloned().unwrap_or_default()
+    }
+
+    pub fn random_hangover_tip(&self) -> String {
+        self.hangover_tips.choose(&mut rand::thread_rng()).cloned().unwrap_or_default()
+    }
+}
+
+fn read<T: serde::de::DeserializeOwned>(dir: &Path, name: &str) -> Result<T, ContentError> {
+    let path = dir.join(name);
+    let data = fs::read_to_string(&path).map_err(|e| ContentError::Io(path.clone(), e))?;
+    toml::from_str(&data).map_err(|e| ContentError::Toml(path, e))
+}
+
+#[derive(Debug)]
+pub enum ContentError {
+    Io(PathBuf, std::io::Error),
+    Toml(PathBuf, toml::de::Error),
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ContentError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ContentError::Io(path, e) => write!(f, "не удалось прочитать {}: {}", path.display(), e),
+            ContentError::Toml(path, e) => write!(f, "ошибка в {}: {}", path.display(), e),
+            ContentError::Invalid(problems) => write!(f, "ошибки в контенте: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ContentError {}
+
+// Пустой текст Telegram отправить не даст, поэтому сообщаем о каждом по номеру
+fn check_texts(problems: &mut Vec<String>, file: &str, what: &str, texts: &[String]) {
+    if texts.is_empty() {
+        problems.push(format!("{}: нет ни одного {}", file, what));
+    }
+    for (i, text) in texts.iter().enumerate() {
+        if text.trim().is_empty() {
+            problems.push(format!("{}: запись №{} пустая", file, i + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content_dir() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("content")
+    }
+
+    fn shipped() -> ContentPack {
+        ContentPack::load(&content_dir().join("ru")).unwrap()
+    }
+
+    #[test]
+    fn shipped_content_is_valid() {
+        let packs = ContentPack::load_all(&content_dir()).unwrap();
+        assert_eq!(packs[&Lang::Ru].roll_message(10), "🎉🎉🎉 Поздравляю! +10 см! Ты настоящий гигант! 💪🍆👑");
+        assert_eq!(packs[&Lang::Uk].roll_message(10), "🎉🎉🎉 Вітаю! +10 см! Ти справжній велетень! 💪🍆👑");
+        // У каждого упражнения в переводах та же вероятность успеха
+        let rates = |lang| packs[&lang].exercises.iter().map(|e| e.success_rate).collect::<Vec<_>>();
+        assert_eq!(rates(Lang::En), rates(Lang::Ru));
+    }
+
+    #[test]
+    fn reports_bad_rate_and_gaps() {
+        let mut pack = shipped();
+        pack.exercises[0].success_rate = 1.5;
+        pack.rolls.retain(|bucket| bucket.min != 0);
+
+        let problems = pack.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("success_rate"));
+        assert!(problems[1].ends_with("изменений 0"));
+    }
+
+    #[test]
+    fn reports_each_blank_text() {
+        let mut pack = shipped();
+        pack.wisdoms.insert(1, "  ".to_string());
+        pack.jokes.push(String::new());
+
+        let problems = pack.validate();
+        assert_eq!(problems, ["wisdoms.toml: запись №2 пустая".to_string(), format!("jokes.toml: запись №{} пустая", pack.jokes.len())]);
+    }
+
+    #[test]
+    fn rolls_substitute_centimetres() {
+        let pack = ContentPack {
+            rolls: toml::from_str::<RollsFile>(
+                r#"
+                [[bucket]]
+                min = -10
+                max = 10
+                messages = ["минус {cm}"]
+                "#,
+            ).unwrap().bucket,
+            ..shipped()
+        };
+        assert_eq!(pack.roll_message(-4), "минус 4");
+    }
+}
@@ -0,0 +1,136 @@
+//! Дневные лимиты команд.
+//!
+//! «Игровой день» начинается в `reset_hour` по местному времени чата и длится до того же
+//! часа следующего дня. Команду с дневным лимитом можно вызвать, если прошлый вызов был
+//! в одном из предыдущих игровых дней. Все даты хранятся в UTC, а в местное время
+//! переводятся только здесь.
+
+use chrono::{DateTime, Duration, NaiveDate, TimeZone, Utc};
+use chrono_tz::Tz;
+use crate::i18n::{t, Lang};
+
+#[derive(Clone, Copy, Debug)]
+pub struct DailyReset {
+    pub timezone: Tz,
+    pub reset_hour: u32,
+}
+
+impl DailyReset {
+    pub fn new(timezone: Tz, reset_hour: u32) -> Self {
+        Self {
+            timezone,
+            reset_hour: reset_hour.min(23),
+        }
+    }
+
+    /// Игровой день, к которому относится момент времени.
+    pub fn game_day(&self, at: DateTime<Utc>) -> NaiveDate {
+        let local = at.with_timezone(&self.timezone).naive_local();
+        (local - Duration::hours(self.reset_hour as i64)).date()
+    }
+
+    /// Можно ли снова вызвать команду, если прошлый раз был в `last`.
+    pub fn is_available(&self, last: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+        self.game_day(last) < self.game_day(now)
+    }
+
+    /// Момент, когда начнётся следующий игровой день.
+    pub fn next_reset(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.day_start(self.game_day(now) + Duration::days(1))
+    }
+
+    /// Момент начала игрового дня.
+    pub fn day_start(&self, day: NaiveDate) -> DateTime<Utc> {
+        let mut local = day.and_hms_opt(self.reset_hour, 0, 0).unwrap();
+        // Если час сброса попал в переход на летнее время, берём первый существующий момент
+        loop {
+            if let Some(at) = self.timezone.from_local_datetime(&local).earliest() {
+                return at.with_timezone(&Utc);
+            }
+            local += Duration::minutes(30);
+        }
+    }
+
+    /// Сколько игровых дней прошло между двумя моментами: 0 — тот же день, 1 — соседние.
+    pub fn days_between(&self, last: DateTime<Utc>, now: DateTime<Utc>) -> i64 {
+        (self.game_day(now) - self.game_day(last)).num_days()
+    }
+
+    /// Сколько осталось до следующей попытки.
+    pub fn time_until_reset(&self, now: DateTime<Utc>) -> Duration {
+        self.next_reset(now) - now
+    }
+}
+
+/// Человекочитаемый интервал на языке чата: «2 дн 5 ч», «3 ч 12 мин», «12 мин», «меньше минуты».
+pub fn format_wait(lang: Lang, wait: Duration) -> String {
+    let minutes = (wait.num_seconds() + 59) / 60;
+    let hours = minutes / 60;
+    if hours >= 24 {
+        return match (hours / 24, hours % 24) {
+            (days, 0) => t!(lang, "wait.days", days = days),
+            (days, hours) => t!(lang, "wait.days_hours", days = days, hours = hours),
+        };
+    }
+    match (hours, minutes % 60) {
+        (0, 0) => t!(lang, "wait.less_than_minute"),
+        (0, minutes) => t!(lang, "wait.minutes", minutes = minutes),
+        (hours, 0) => t!(lang, "wait.hours", hours = hours),
+        (hours, minutes) => t!(lang, "wait.hours_minutes", hours = hours, minutes = minutes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(y: i32, mo: u32, d: u32, h: u32, mi: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, mo, d, h, mi, 0).unwrap()
+    }
+
+    #[test]
+    fn resets_at_local_hour() {
+        // Омск UTC+6, сброс в 06:00 местного = 00:00 UTC
+        let reset = DailyReset::new(chrono_tz::Asia::Omsk, 6);
+        let last = utc(2024, 11, 20, 23, 0);
+
+        assert!(!reset.is_available(last, utc(2024, 11, 20, 23, 59)));
+        assert!(reset.is_available(last, utc(2024, 11, 21, 0, 0)));
+        assert_eq!(reset.next_reset(utc(2024, 11, 20, 23, 0)), utc(2024, 11, 21, 0, 0));
+    }
+
+    #[test]
+    fn midnight_in_chat_timezone_not_server() {
+        let reset = DailyReset::new(chrono_tz::Europe::Moscow, 0);
+        // 20:59 и 21:01 UTC — это 23:59 и 00:01 по Москве
+        assert!(reset.is_available(utc(2024, 11, 20, 20, 59), utc(2024, 11, 20, 21, 1)));
+        assert_eq!(reset.time_until_reset(utc(2024, 11, 20, 20, 59)), Duration::minutes(1));
+    }
+
+    #[test]
+    fn skips_dst_gap() {
+        // В Берлине 31.03.2024 часы перевели с 02:00 сразу на 03:00
+        let reset = DailyReset::new(chrono_tz::Europe::Berlin, 2);
+        let next = reset.next_reset(utc(2024, 3, 30, 12, 0));
+        assert_eq!(next, utc(2024, 3, 31, 1, 0));
+    }
+
+    #[test]
+    fn counts_days_by_reset_hour() {
+        let reset = DailyReset::new(chrono_tz::UTC, 6);
+        // 05:00 ещё относится к предыдущему игровому дню
+        assert_eq!(reset.days_between(utc(2024, 11, 19, 7, 0), utc(2024, 11, 20, 5, 0)), 0);
+        assert_eq!(reset.days_between(utc(2024, 11, 19, 7, 0), utc(2024, 11, 20, 6, 0)), 1);
+        assert_eq!(reset.days_between(utc(2024, 11, 17, 23, 0), utc(2024, 11, 20, 6, 0)), 3);
+    }
+
+    #[test]
+    fn formats_wait() {
+        assert_eq!(format_wait(Lang::Ru, Duration::seconds(30)), "1 мин");
+        assert_eq!(format_wait(Lang::Ru, Duration::seconds(0)), "меньше минуты");
+        assert_eq!(format_wait(Lang::Ru, Duration::minutes(192)), "3 ч 12 мин");
+        assert_eq!(format_wait(Lang::Ru, Duration::hours(5)), "5 ч");
+        assert_eq!(format_wait(Lang::Ru, Duration::hours(53) + Duration::minutes(10)), "2 дн 5 ч");
+        assert_eq!(format_wait(Lang::En, Duration::minutes(192)), "3 h 12 min");
+    }
+}
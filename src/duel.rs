@@ -0,0 +1,165 @@
+//! Дуэли: один участник вызывает другого и ставит сантиметры.
+//! Вызовы живут только в памяти и пропадают по таймауту или при перезапуске.
+
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use rand::Rng;
+use crate::models::UserData;
+
+#[derive(Clone, Copy, Debug)]
+pub struct DuelSettings {
+    /// Сколько ждать между дуэлями одному участнику
+    pub cooldown: Duration,
+    /// Сколько вызов ждёт ответа, прежде чем отмениться
+    pub timeout: Duration,
+}
+
+impl Default for DuelSettings {
+    fn default() -> Self {
+        Self {
+            cooldown: Duration::hours(1),
+            timeout: Duration::minutes(2),
+        }
+    }
+}
+
+impl DuelSettings {
+    /// Сколько пользователю осталось ждать до следующей дуэли, если откат ещё идёт.
+    pub fn cooldown_left(&self, user: &UserData, now: DateTime<Utc>) -> Option<Duration> {
+        let ready_at = user.last_duel? + self.cooldown;
+        (ready_at > now).then(|| ready_at - now)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Duel {
+    pub id: u64,
+    pub chat_id: i64,
+    pub challenger_id: i64,
+    pub target_id: i64,
+    pub stake: i32,
+}
+
+/// Итог дуэли с учётом того, что проигравший не уходит ниже нуля.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DuelOutcome {
+    pub winner_id: i64,
+    pub loser_id: i64,
+    /// Сколько сантиметров реально перешло от проигравшего к победителю
+    pub transfer: i32,
+}
+
+impl Duel {
+    /// Шанс вызвавшего на победу пропорционален размерам: (c + 1) / (c + t + 2).
+    /// Единица добавлена, чтобы у участника с нулём оставались шансы.
+    pub fn challenger_win_chance(challenger_size: i32, target_size: i32) -> f64 {
+        let challenger = challenger_size.max(0) as f64 + 1.0;
+        let target = target_size.max(0) as f64 + 1.0;
+        challenger / (challenger + target)
+    }
+
+    pub fn resolve(&self, challenger_size: i32, target_size: i32) -> DuelOutcome {
+        let chance = Self::challenger_win_chance(challenger_size, target_size);
+        let challenger_wins = rand::thread_rng().gen_bool(chance);
+        self.outcome(challenger_wins, challenger_size, target_size)
+    }
+
+    fn outcome(&self, challenger_wins: bool, challenger_size: i32, target_size: i32) -> DuelOutcome {
+        let (winner_id, loser_id, loser_size) = if challenger_wins {
+            (self.challenger_id, self.target_id, target_size)
+        } else {
+            (self.target_id, self.challenger_id, challenger_size)
+        };
+        DuelOutcome {
+            winner_id,
+            loser_id,
+            transfer: self.stake.min(loser_size.max(0)),
+        }
+    }
+}
+
+/// Ожидающие ответа вызовы.
+#[derive(Default)]
+pub struct DuelBoard {
+    next_id: u64,
+    pending: HashMap<u64, Duel>,
+}
+
+impl DuelBoard {
+    pub fn create(&mut self, chat_id: i64, challenger_id: i64, target_id: i64, stake: i32) -> Duel {
+        self.next_id += 1;
+        let duel = Duel {
+            id: self.next_id,
+            chat_id,
+            challenger_id,
+            target_id,
+            stake,
+        };
+        self.pending.insert(duel.id, duel.clone());
+        duel
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Duel> {
+        self.pending.get(&id)
+    }
+
+    pub fn take(&mut self, id: u64) -> Option<Duel> {
+        self.pending.remove(&id)
+    }
+
+    /// Есть ли у пользователя неотвеченный вызов в чате (как у вызвавшего или вызванного).
+    pub fn is_busy(&self, chat_id: i64, user_id: i64) -> bool {
+        self.pending.values().any(|d| {
+            d.chat_id == chat_id && (d.challenger_id == user_id || d.target_id == user_id)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn duel(stake: i32) -> Duel {
+        DuelBoard::default().create(1, 10, 20, stake)
+    }
+
+    #[test]
+    fn chance_follows_sizes() {
+        assert_eq!(Duel::challenger_win_chance(0, 0), 0.5);
+        assert_eq!(Duel::challenger_win_chance(9, 29), 0.25);
+        assert!(Duel::challenger_win_chance(0, 100) > 0.0);
+    }
+
+    #[test]
+    fn loser_never_goes_below_zero() {
+        let outcome = duel(10).outcome(true, 30, 4);
+        assert_eq!(outcome, DuelOutcome { winner_id: 10, loser_id: 20, transfer: 4 });
+
+        let outcome = duel(10).outcome(false, 0, 30);
+        assert_eq!(outcome.transfer, 0);
+    }
+
+    #[test]
+    fn cooldown_counts_from_last_duel() {
+        let settings = DuelSettings::default();
+        let now = Utc::now();
+        let mut user = UserData::new(1, 10);
+
+        assert!(settings.cooldown_left(&user, now).is_none());
+        user.last_duel = Some(now - Duration::minutes(20));
+        assert_eq!(settings.cooldown_left(&user, now), Some(Duration::minutes(40)));
+        user.last_duel = Some(now - Duration::hours(2));
+        assert!(settings.cooldown_left(&user, now).is_none());
+    }
+
+    #[test]
+    fn tracks_busy_users() {
+        let mut board = DuelBoard::default();
+        let duel = board.create(1, 10, 20, 5);
+
+        assert!(board.is_busy(1, 20));
+        assert!(!board.is_busy(2, 20));
+        board.take(duel.id);
+        assert!(!board.is_busy(1, 10));
+    }
+}
@@ -0,0 +1,181 @@
+//! Ошибки бота. Обработчики возвращают [`Error`], а диспетчер передаёт её в [`report`]:
+//! ошибка попадает в лог вместе с чатом, пользователем и командой, а пользователь получает извинение
+//! вместо молчания.
+
+use std::fmt;
+use teloxide::payloads::AnswerCallbackQuerySetters;
+use teloxide::prelude::{Message, Requester};
+use teloxide::types::{CallbackQuery, ChatId, Poll, UserId};
+use teloxide::{Bot, RequestError};
+use crate::chart::ChartError;
+use crate::config::SharedConfig;
+use crate::content::ContentError;
+use crate::http::FetchError;
+use crate::i18n::t;
+use crate::storage::StorageError;
+use crate::weather::WeatherError;
+
+#[derive(Debug)]
+pub enum Error {
+    Telegram(RequestError),
+    Storage(StorageError),
+    /// Внешний сервис: погода, анекдоты, мемы, TMDB
+    External(FetchError),
+    /// Данные не удалось разобрать
+    Parse(String),
+    /// Неверные настройки в окружении или в файлах контента
+    Config(String),
+    Chart(ChartError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Telegram(e) => write!(f, "ошибка Telegram: {}", e),
+            Error::Storage(e) => write!(f, "ошибка хранилища: {}", e),
+            Error::External(e) => write!(f, "ошибка внешнего сервиса: {}", e),
+            Error::Parse(e) => write!(f, "ошибка разбора: {}", e),
+            Error::Config(e) => write!(f, "ошибка настройки: {}", e),
+            Error::Chart(e) => write!(f, "ошибка графика: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Telegram(e) => Some(e),
+            Error::Storage(e) => Some(e),
+            Error::External(e) => Some(e),
+            Error::Chart(e) => Some(e),
+            Error::Parse(_) | Error::Config(_) => None,
+        }
+    }
+}
+
+impl From<RequestError> for Error {
+    fn from(e: RequestError) -> Self { Error::Telegram(e) }
+}
+
+impl From<StorageError> for Error {
+    fn from(e: StorageError) -> Self { Error::Storage(e) }
+}
+
+impl From<FetchError> for Error {
+    fn from(e: FetchError) -> Self { Error::External(e) }
+}
+
+impl From<WeatherError> for Error {
+    fn from(e: WeatherError) -> Self {
+        match e {
+            WeatherError::Fetch(e) => Error::External(e),
+            e => Error::Parse(e.to_string()),
+        }
+    }
+}
+
+impl From<ContentError> for Error {
+    fn from(e: ContentError) -> Self { Error::Config(e.to_string()) }
+}
+
+impl From<ChartError> for Error {
+    fn from(e: ChartError) -> Self { Error::Chart(e) }
+}
+
+/// Откуда пришло обновление, которое не удалось обработать.
+pub struct ErrorContext {
+    chat_id: Option<ChatId>,
+    user_id: Option<UserId>,
+    /// Команда, данные кнопки или id опроса
+    action: String,
+    apology: Apology,
+}
+
+// Как извиниться: сообщением в чат, всплывающим окном у кнопки или никак (у опроса нет автора)
+enum Apology {
+    Message(ChatId),
+    Callback(String),
+    None,
+}
+
+impl ErrorContext {
+    pub fn message(msg: &Message) -> Self {
+        ErrorContext {
+            chat_id: Some(msg.chat.id),
+            user_id: msg.from.as_ref().map(|user| user.id),
+            action: msg.text().and_then(|text| text.split_whitespace().next()).unwrap_or_default().to_string(),
+            apology: Apology::Message(msg.chat.id),
+        }
+    }
+
+    pub fn callback(q: &CallbackQuery) -> Self {
+        ErrorContext {
+            chat_id: q.message.as_ref().map(|message| message.chat().id),
+            user_id: Some(q.from.id),
+            action: format!("кнопка {}", q.data.as_deref().unwrap_or_default()),
+            apology: Apology::Callback(q.id.clone()),
+        }
+    }
+
+    pub fn poll(poll: &Poll) -> Self {
+        ErrorContext {
+            chat_id: None,
+            user_id: None,
+            action: format!("опрос {}", poll.id),
+            apology: Apology::None,
+        }
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let or_dash = |id: Option<String>| id.unwrap_or_else(|| "-".to_string());
+        write!(
+            f,
+            "чат {}, пользователь {}, {}",
+            or_dash(self.chat_id.map(|id| id.to_string())),
+            or_dash(self.user_id.map(|id| id.to_string())),
+            self.action
+        )
+    }
+}
+
+/// Пишет ошибку обработчика в лог и извиняется перед пользователем на языке чата.
+/// Ошибка возвращается, только если не удалось извиниться; её логирует сам диспетчер.
+pub async fn report(bot: &Bot, config: &SharedConfig, context: ErrorContext, result: Result<(), Error>) -> Result<(), Error> {
+    let Err(error) = result else {
+        return Ok(());
+    };
+    log::error!("{}: {}", context, error);
+
+    let lang = {
+        let mut config = config.lock().await;
+        let default = config.get_default_language();
+        // Хранилище может быть причиной ошибки, тогда извиняемся на языке по умолчанию
+        context.chat_id.and_then(|chat_id| config.get_language(chat_id.0).ok()).unwrap_or(default)
+    };
+    match context.apology {
+        Apology::Message(chat_id) => {
+            bot.send_message(chat_id, t!(lang, "error.apology")).await?;
+        }
+        Apology::Callback(id) => {
+            bot.answer_callback_query(id).text(t!(lang, "error.apology")).show_alert(true).await?;
+        }
+        Apology::None => {}
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weather_fetch_errors_are_external() {
+        let error: Error = WeatherError::Fetch(FetchError::CircuitOpen).into();
+        assert!(matches!(error, Error::External(FetchError::CircuitOpen)));
+        let error: Error = WeatherError::Incomplete("прогноза на сегодня").into();
+        assert!(matches!(error, Error::Parse(_)));
+        assert_eq!(error.to_string(), "ошибка разбора: в ответе нет прогноза на сегодня");
+    }
+}
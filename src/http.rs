@@ -0,0 +1,466 @@
+//! Общий HTTP-клиент для внешних источников: погоды, анекдотов, мемов и фильмов.
+//! Ответы источников, которые меняются редко, кэшируются на время жизни источника,
+//! чтобы одинаковые запросы подряд не ходили в сеть.
+//!
+//! У каждого источника своя [`Policy`]: время ожидания ответа, число повторов с растущей паузой
+//! и предохранитель, который после нескольких сбоев подряд на время перестаёт дёргать упавший сайт.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Source {
+    Weather,
+    Geocoding,
+    Jokes,
+    Anekdots,
+    Memes,
+    MemeApi,
+    /// Проверка типа файлов мемов на сторонних хостингах картинок
+    MemeFiles,
+    Tmdb,
+}
+
+impl Source {
+    pub const ALL: [Source; 8] = [
+        Source::Weather,
+        Source::Geocoding,
+        Source::Jokes,
+        Source::Anekdots,
+        Source::Memes,
+        Source::MemeApi,
+        Source::MemeFiles,
+        Source::Tmdb,
+    ];
+
+    /// Ключ источника в каталогах сообщений и в переменных окружения (`JOKES_TIMEOUT_SECONDS`).
+    pub fn key(self) -> &'static str {
+        match self {
+            Source::Weather => "weather",
+            Source::Geocoding => "geocoding",
+            Source::Jokes => "jokes",
+            Source::Anekdots => "anekdots",
+            Source::Memes => "memes",
+            Source::MemeApi => "memeapi",
+            Source::MemeFiles => "memefiles",
+            Source::Tmdb => "tmdb",
+        }
+    }
+
+    /// Имя сайта для логов.
+    pub fn name(self) -> &'static str {
+        match self {
+            Source::Weather => "open-meteo",
+            Source::Geocoding => "geocoding",
+            Source::Jokes => "baneks",
+            Source::Anekdots => "anekdot.ru/anekdot",
+            Source::Memes => "anekdot.ru/mem",
+            Source::MemeApi => "meme-api.com",
+            Source::MemeFiles => "meme files",
+            Source::Tmdb => "tmdb",
+        }
+    }
+
+    /// Сколько хранить ответ. Случайные анекдоты и мемы не кэшируются — иначе они перестанут быть случайными.
+    fn cache_ttl(self) -> Option<Duration> {
+        match self {
+            Source::Weather => Some(Duration::from_secs(10 * 60)),
+            Source::Geocoding | Source::Tmdb => Some(Duration::from_secs(24 * 60 * 60)),
+            Source::Jokes | Source::Anekdots | Source::Memes | Source::MemeApi | Source::MemeFiles => None,
+        }
+    }
+}
+
+/// Как обращаться с источником, если он отвечает медленно или с ошибками.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Policy {
+    pub timeout: Duration,
+    /// Сколько раз повторить запрос после сетевой ошибки или ответа 5xx
+    pub retries: u32,
+    /// Пауза перед первым повтором, дальше она удваивается
+    pub backoff: Duration,
+    /// После стольких неудач подряд источник считается упавшим
+    pub breaker_failures: u32,
+    /// Сколько не обращаться к упавшему источнику
+    pub breaker_cooldown: Duration,
+}
+
+impl Policy {
+    /// Значения по умолчанию: сайты со скрейпингом медленнее API, а погоду ждут меньше всего.
+    /// Файлы мемов лежат на разных хостингах: их не повторяем, а сразу берём следующий,
+    /// и предохранитель срабатывает только после многих сбоев подряд.
+    pub fn for_source(source: Source) -> Self {
+        let timeout = match source {
+            Source::Weather | Source::Geocoding | Source::MemeFiles => 5,
+            Source::Tmdb => 8,
+            Source::Jokes | Source::Anekdots | Source::Memes | Source::MemeApi => 10,
+        };
+        let policy = Policy {
+            timeout: Duration::from_secs(timeout),
+            retries: 2,
+            backoff: Duration::from_millis(500),
+            breaker_failures: 5,
+            breaker_cooldown: Duration::from_secs(5 * 60),
+        };
+        match source {
+            Source::MemeFiles => Policy { retries: 0, breaker_failures: 20, breaker_cooldown: Duration::from_secs(60), ..policy },
+            _ => policy,
+        }
+    }
+
+    /// Больше повторов не имеет смысла: паузы между ними растут вдвое.
+    pub const MAX_RETRIES: u32 = 5;
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.backoff.checked_mul(2u32.saturating_pow(attempt)).unwrap_or(Duration::MAX)
+    }
+
+    // Сколько самое большее длится запрос со всеми повторами и паузами между ними.
+    // Ошибка в настройках не должна ронять запрос, поэтому без переполнений
+    fn attempts_budget(&self) -> Duration {
+        self.timeout
+            .checked_mul(self.retries.saturating_add(1))
+            .unwrap_or(Duration::MAX)
+            .saturating_add(self.backoff(self.retries))
+    }
+}
+
+#[derive(Debug)]
+pub enum FetchError {
+    Http(reqwest::Error),
+    Status(reqwest::StatusCode),
+    /// Источник недавно падал, запрос даже не отправлялся
+    CircuitOpen,
+    /// Ответ пришёл, но нужного в нём нет (на странице не нашлось анекдота и т. п.)
+    Invalid(String),
+    /// Источник не настроен, например нет ключа API
+    NotConfigured(&'static str),
+}
+
+impl FetchError {
+    // 405 на HEAD значит лишь, что сервер не поддерживает метод, а не что он упал
+    fn counts_as_failure(&self) -> bool {
+        !matches!(self, FetchError::Status(reqwest::StatusCode::METHOD_NOT_ALLOWED))
+    }
+
+    // Повторять есть смысл при сетевых сбоях и ошибках сервера, но не при 404 или неверном ключе
+    fn is_transient(&self) -> bool {
+        match self {
+            FetchError::Http(e) => e.is_timeout() || e.is_connect() || e.is_request() || e.is_body(),
+            FetchError::Status(status) => status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Http(e) => write!(f, "ошибка запроса: {}", e),
+            FetchError::Status(status) => write!(f, "сервер ответил {}", status),
+            FetchError::CircuitOpen => write!(f, "источник недавно падал, запросы временно не отправляются"),
+            FetchError::Invalid(reason) => write!(f, "неожиданный ответ: {}", reason),
+            FetchError::NotConfigured(what) => write!(f, "не задан {}", what),
+        }
+    }
+}
+
+impl std::error::Error for FetchError {}
+
+impl From<reqwest::Error> for FetchError {
+    // Адрес из ошибки убираем: в нём бывают ключи API, а ошибки попадают в лог
+    fn from(e: reqwest::Error) -> Self {
+        FetchError::Http(e.without_url())
+    }
+}
+
+/// Один клиент на весь процесс: соединения переиспользуются, кэш и предохранители общие для всех чатов.
+#[derive(Clone)]
+pub struct HttpClient {
+    client: reqwest::Client,
+    policies: Arc<HashMap<Source, Policy>>,
+    cache: Arc<Mutex<Cache>>,
+    breakers: Arc<Mutex<HashMap<Source, Breaker>>>,
+}
+
+impl HttpClient {
+    /// Источники без своей политики получают [`Policy::for_source`].
+    pub fn new(policies: HashMap<Source, Policy>) -> Self {
+        HttpClient {
+            client: reqwest::Client::new(),
+            policies: Arc::new(policies),
+            cache: Arc::default(),
+            breakers: Arc::default(),
+        }
+    }
+
+    fn policy(&self, source: Source) -> Policy {
+        self.policies.get(&source).cloned().unwrap_or_else(|| Policy::for_source(source))
+    }
+
+    /// Тело ответа на GET-запрос. Ключ кэша — источник и полный адрес вместе с параметрами.
+    pub async fn get(&self, source: Source, url: &str, query: &[(&str, &str)]) -> Result<String, FetchError> {
+        let policy = self.policy(source);
+        let request = self.client.get(url).query(query).timeout(policy.timeout).build()?;
+        let key = (source, request.url().to_string());
+        let ttl = source.cache_ttl();
+
+        if ttl.is_some() {
+            let mut cache = self.cache.lock().unwrap();
+            let cached = cache.get(&key, Instant::now());
+            let (hits, misses) = cache.stats(source);
+            match cached {
+                Some(body) => {
+                    log::debug!("Кэш {}: попадание (попаданий {}, промахов {})", source.name(), hits, misses);
+                    return Ok(body);
+                }
+                None => log::debug!("Кэш {}: промах (попаданий {}, промахов {})", source.name(), hits, misses),
+            }
+        }
+
+        let body = self.execute(source, &policy, request, false).await?;
+        if let Some(ttl) = ttl {
+            self.cache.lock().unwrap().insert(key, body.clone(), Instant::now(), ttl);
+        }
+        Ok(body)
+    }
+
+    /// Тип содержимого по адресу без скачивания самого файла. Не кэшируется.
+    /// Если сервер не принимает HEAD, спрашивает первый байт файла обычным GET.
+    pub async fn content_type(&self, source: Source, url: &reqwest::Url) -> Result<String, FetchError> {
+        let policy = self.policy(source);
+        let request = self.client.head(url.clone()).timeout(policy.timeout).build()?;
+        match self.execute(source, &policy, request, true).await {
+            Err(FetchError::Status(reqwest::StatusCode::METHOD_NOT_ALLOWED)) => {
+                let request = self.client.get(url.clone()).header(reqwest::header::RANGE, "bytes=0-0").timeout(policy.timeout).build()?;
+                self.execute(source, &policy, request, true).await
+            }
+            result => result,
+        }
+    }
+
+    // Запрос с повторами и через предохранитель источника
+    async fn execute(&self, source: Source, policy: &Policy, request: reqwest::Request, content_type: bool) -> Result<String, FetchError> {
+        if !self.breakers.lock().unwrap().entry(source).or_default().allows(Instant::now(), policy) {
+            return Err(FetchError::CircuitOpen);
+        }
+
+        let mut attempt = 0;
+        let result = loop {
+            // GET и HEAD без тела клонируются всегда, но на всякий случай не паникуем
+            let Some(request) = request.try_clone() else {
+                break Err(FetchError::Invalid("запрос с потоковым телом нельзя повторить".to_string()));
+            };
+            match self.send(request, content_type).await {
+                Err(e) if e.is_transient() && attempt < policy.retries => {
+                    let pause = policy.backoff(attempt);
+                    log::info!("{}: {}, повтор через {:?}", source.name(), e, pause);
+                    tokio::time::sleep(pause).await;
+                    attempt += 1;
+                }
+                result => break result,
+            }
+        };
+
+        let success = result.as_ref().map_or_else(|e| !e.counts_as_failure(), |_| true);
+        let opened = self.breakers.lock().unwrap().entry(source).or_default().record(success, Instant::now(), policy);
+        if opened {
+            log::warn!("{}: {} сбоев подряд, не обращаемся {:?}", source.name(), policy.breaker_failures, policy.breaker_cooldown);
+        }
+        result
+    }
+
+    // Тело ответа, а если нужен только тип — заголовок Content-Type (пустой, если его нет); тело тогда не читается
+    async fn send(&self, request: reqwest::Request, content_type: bool) -> Result<String, FetchError> {
+        let response = self.client.execute(request).await?;
+        if !response.status().is_success() {
+            return Err(FetchError::Status(response.status()));
+        }
+        if content_type {
+            let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|value| value.to_str().ok());
+            return Ok(content_type.unwrap_or_default().to_string());
+        }
+        Ok(response.text().await?)
+    }
+}
+
+/// Предохранитель источника: считает неудачи подряд и после порога закрывает доступ на время.
+/// Когда время выходит, пропускает ровно один запрос на пробу, а остальным сразу отказывает:
+/// успех пробы сбрасывает счётчик, неудача снова закрывает.
+#[derive(Default)]
+struct Breaker {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// До каких пор идёт проба. Если её запрос так и не закончился, после этого пропускается следующая
+    probe_until: Option<Instant>,
+}
+
+impl Breaker {
+    fn allows(&mut self, now: Instant, policy: &Policy) -> bool {
+        match self.open_until {
+            None => true,
+            Some(until) if now < until => false,
+            Some(_) if self.probe_until.is_some_and(|probe| now < probe) => false,
+            Some(_) => {
+                self.probe_until = Some(now + policy.attempts_budget());
+                true
+            }
+        }
+    }
+
+    /// Возвращает true, если предохранитель только что сработал.
+    fn record(&mut self, success: bool, now: Instant, policy: &Policy) -> bool {
+        if success {
+            *self = Breaker::default();
+            return false;
+        }
+        self.failures += 1;
+        if self.failures >= policy.breaker_failures {
+            self.open_until = Some(now + policy.breaker_cooldown);
+            self.probe_until = None;
+            return true;
+        }
+        false
+    }
+}
+
+type Key = (Source, String);
+
+#[derive(Default)]
+struct Cache {
+    entries: HashMap<Key, (Instant, String)>,
+    // Попадания и промахи по источникам
+    stats: HashMap<Source, (u64, u64)>,
+}
+
+impl Cache {
+    fn get(&mut self, key: &Key, now: Instant) -> Option<String> {
+        let body = self.entries.get(key).filter(|(expires, _)| *expires > now).map(|(_, body)| body.clone());
+        let stats = self.stats.entry(key.0).or_default();
+        match body {
+            Some(_) => stats.0 += 1,
+            None => stats.1 += 1,
+        }
+        body
+    }
+
+    fn insert(&mut self, key: Key, body: String, now: Instant, ttl: Duration) {
+        // Заодно выбрасываем устаревшее, чтобы кэш не рос без конца
+        self.entries.retain(|_, (expires, _)| *expires > now);
+        self.entries.insert(key, (now + ttl, body));
+    }
+
+    fn stats(&self, source: Source) -> (u64, u64) {
+        self.stats.get(&source).copied().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_expire_and_are_counted() {
+        let mut cache = Cache::default();
+        let now = Instant::now();
+        let key = (Source::Weather, "https://example.com/?city=omsk".to_string());
+        assert_eq!(cache.get(&key, now), None);
+
+        cache.insert(key.clone(), "ясно".to_string(), now, Duration::from_secs(600));
+        assert_eq!(cache.get(&key, now + Duration::from_secs(599)).as_deref(), Some("ясно"));
+        assert_eq!(cache.get(&key, now + Duration::from_secs(600)), None);
+        assert_eq!(cache.stats(Source::Weather), (1, 2));
+        assert_eq!(cache.stats(Source::Tmdb), (0, 0));
+    }
+
+    #[test]
+    fn keys_include_source_and_parameters() {
+        let mut cache = Cache::default();
+        let now = Instant::now();
+        cache.insert((Source::Weather, "a?x=1".to_string()), "1".to_string(), now, Duration::from_secs(60));
+        assert_eq!(cache.get(&(Source::Weather, "a?x=2".to_string()), now), None);
+        assert_eq!(cache.get(&(Source::Geocoding, "a?x=1".to_string()), now), None);
+    }
+
+    #[test]
+    fn breaker_opens_after_failures_and_probes_after_cooldown() {
+        let policy = Policy { breaker_failures: 3, breaker_cooldown: Duration::from_secs(60), ..Policy::for_source(Source::Jokes) };
+        let mut breaker = Breaker::default();
+        let now = Instant::now();
+
+        assert!(!breaker.record(false, now, &policy));
+        assert!(!breaker.record(false, now, &policy));
+        assert!(breaker.allows(now, &policy));
+        assert!(breaker.record(false, now, &policy));
+        assert!(!breaker.allows(now + Duration::from_secs(59), &policy));
+
+        // После паузы проходит только одна проба; она не удалась — снова закрыт
+        let later = now + Duration::from_secs(60);
+        assert!(breaker.allows(later, &policy));
+        assert!(!breaker.allows(later, &policy));
+        assert!(breaker.record(false, later, &policy));
+        assert!(!breaker.allows(later + Duration::from_secs(1), &policy));
+
+        // Удачная проба сбрасывает счётчик
+        let much_later = later + Duration::from_secs(60);
+        assert!(breaker.allows(much_later, &policy));
+        assert!(!breaker.record(true, much_later, &policy));
+        assert!(!breaker.record(false, much_later, &policy));
+        assert!(breaker.allows(much_later, &policy));
+        assert!(breaker.allows(much_later, &policy));
+    }
+
+    #[test]
+    fn stuck_probe_lets_the_next_one_through() {
+        let policy = Policy { breaker_failures: 1, breaker_cooldown: Duration::from_secs(60), ..Policy::for_source(Source::Jokes) };
+        let mut breaker = Breaker::default();
+        let now = Instant::now();
+        breaker.record(false, now, &policy);
+
+        let later = now + Duration::from_secs(60);
+        assert!(breaker.allows(later, &policy));
+        // Ответа на пробу нет дольше, чем могут идти все её попытки
+        assert!(!breaker.allows(later + policy.attempts_budget() - Duration::from_secs(1), &policy));
+        assert!(breaker.allows(later + policy.attempts_budget(), &policy));
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = Policy::for_source(Source::Tmdb);
+        assert_eq!(policy.backoff(0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(2), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn huge_retries_do_not_overflow() {
+        let policy = Policy { retries: u32::MAX, ..Policy::for_source(Source::Jokes) };
+        assert!(policy.attempts_budget() > policy.backoff(policy.retries));
+        let policy = Policy { retries: 3, timeout: Duration::MAX, backoff: Duration::MAX, ..Policy::for_source(Source::Jokes) };
+        assert_eq!(policy.backoff(policy.retries), Duration::MAX);
+        assert_eq!(policy.attempts_budget(), Duration::MAX);
+    }
+
+    #[test]
+    fn only_transient_errors_are_retried() {
+        assert!(FetchError::Status(reqwest::StatusCode::BAD_GATEWAY).is_transient());
+        assert!(FetchError::Status(reqwest::StatusCode::TOO_MANY_REQUESTS).is_transient());
+        assert!(!FetchError::Status(reqwest::StatusCode::UNAUTHORIZED).is_transient());
+        assert!(!FetchError::Invalid("пусто".to_string()).is_transient());
+    }
+
+    #[test]
+    fn method_not_allowed_does_not_trip_the_breaker() {
+        assert!(!FetchError::Status(reqwest::StatusCode::METHOD_NOT_ALLOWED).counts_as_failure());
+        assert!(FetchError::Status(reqwest::StatusCode::NOT_FOUND).counts_as_failure());
+        assert!(FetchError::CircuitOpen.counts_as_failure());
+    }
+
+    #[test]
+    fn random_sources_are_not_cached() {
+        assert_eq!(Source::Jokes.cache_ttl(), None);
+        assert_eq!(Source::Memes.cache_ttl(), None);
+        assert_eq!(Source::Weather.cache_ttl(), Some(Duration::from_secs(600)));
+    }
+}
@@ -0,0 +1,223 @@
+//! Переводы ответов бота. Каталоги `locales/<код>.toml` встроены в бинарник,
+//! ключ сообщения — путь по таблицам TOML, например `pisun.cooldown`.
+//!
+//! В тексте `{name}` заменяется значением аргумента, а `{name|форма|форма|форма}` —
+//! формой слова, согласованной с числом `name`: для русского и украинского три формы
+//! (1, 2–4, 5–20), для английского две (1 и остальные).
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::LazyLock;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Lang {
+    Ru,
+    Uk,
+    En,
+}
+
+impl Lang {
+    pub const ALL: [Lang; 3] = [Lang::Ru, Lang::Uk, Lang::En];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Lang::Ru => "ru",
+            Lang::Uk => "uk",
+            Lang::En => "en",
+        }
+    }
+
+    /// Принимает и код с регионом, как в `language_code` у Telegram (`en-US`).
+    pub fn from_code(code: &str) -> Option<Self> {
+        let code = code.split(['-', '_']).next()?.to_lowercase();
+        Self::ALL.into_iter().find(|lang| lang.code() == code)
+    }
+
+    /// Название языка на нём самом, для выбора в /language.
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Lang::Ru => "🇷🇺 Русский",
+            Lang::Uk => "🇺🇦 Українська",
+            Lang::En => "🇬🇧 English",
+        }
+    }
+
+    // Номер формы слова для числа в виде текста. Дробные числа («6.5 сантиметра», «6.5 centimetres»)
+    // склоняются как 2 в русском и украинском и как множественное число в английском
+    fn plural_form(self, value: &str) -> usize {
+        let n = match value.parse::<i64>() {
+            Ok(n) => n.unsigned_abs(),
+            Err(_) if value.parse::<f64>().is_ok() => 2,
+            Err(_) => 0,
+        };
+        match self {
+            Lang::Ru | Lang::Uk => match (n % 10, n % 100) {
+                (1, rem) if rem != 11 => 0,
+                (2..=4, rem) if !(12..=14).contains(&rem) => 1,
+                _ => 2,
+            },
+            Lang::En => usize::from(n != 1),
+        }
+    }
+}
+
+type Catalog = HashMap<String, String>;
+
+static CATALOGS: LazyLock<HashMap<Lang, Catalog>> =
+    LazyLock::new(|| Lang::ALL.into_iter().map(|lang| (lang, parse(source(lang)))).collect());
+
+fn source(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Ru => include_str!("../locales/ru.toml"),
+        Lang::Uk => include_str!("../locales/uk.toml"),
+        Lang::En => include_str!("../locales/en.toml"),
+    }
+}
+
+fn parse(source: &str) -> Catalog {
+    let table: toml::Table = toml::from_str(source).expect("встроенный каталог переводов должен быть корректным TOML");
+    let mut catalog = Catalog::new();
+    flatten("", table, &mut catalog);
+    catalog
+}
+
+fn flatten(prefix: &str, table: toml::Table, catalog: &mut Catalog) {
+    for (name, value) in table {
+        let key = if prefix.is_empty() { name } else { format!("{}.{}", prefix, name) };
+        match value {
+            toml::Value::String(text) => {
+                catalog.insert(key, text);
+            }
+            toml::Value::Table(table) => flatten(&key, table, catalog),
+            other => panic!("в каталоге переводов {} должен быть строкой, а не {}", key, other.type_str()),
+        }
+    }
+}
+
+/// Текст сообщения `key` на языке `lang` с подставленными аргументами.
+/// Если перевода нет, берётся русский текст, а если нет и его — сам ключ.
+pub fn translate(lang: Lang, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    let text = CATALOGS[&lang].get(key).or_else(|| CATALOGS[&Lang::Ru].get(key));
+    match text {
+        Some(text) => render(lang, text, args),
+        None => {
+            log::warn!("Нет перевода для {}", key);
+            key.to_string()
+        }
+    }
+}
+
+/// `t!(lang, "ключ", имя = значение, ...)` — короткая запись для [`translate`].
+/// Аргументы не доживают до конца выражения, так что макрос можно использовать рядом с `.await`.
+macro_rules! t {
+    ($lang:expr, $key:expr $(, $name:ident = $value:expr)* $(,)?) => {{
+        let text = $crate::i18n::translate($lang, &$key, &[$((stringify!($name), &$value as &dyn std::fmt::Display)),*]);
+        text
+    }};
+}
+pub(crate) use t;
+
+fn render(lang: Lang, text: &str, args: &[(&str, &dyn Display)]) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(len) = rest[start..].find('}') else {
+            break;
+        };
+        result.push_str(&rest[..start]);
+        let placeholder = &rest[start + 1..start + len];
+        let (name, forms) = match placeholder.split_once('|') {
+            Some((name, forms)) => (name, Some(forms)),
+            None => (placeholder, None),
+        };
+
+        match (args.iter().find(|(arg, _)| *arg == name), forms) {
+            (Some((_, value)), None) => result.push_str(&value.to_string()),
+            (Some((_, value)), Some(forms)) => {
+                let forms: Vec<_> = forms.split('|').collect();
+                result.push_str(forms.get(lang.plural_form(&value.to_string())).or(forms.last()).unwrap_or(&""));
+            }
+            // Неизвестный аргумент оставляем как есть, чтобы ошибку было видно в чате
+            (None, _) => result.push_str(&rest[start..=start + len]),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeSet;
+
+    fn plural_forms(lang: Lang) -> usize {
+        match lang {
+            Lang::Ru | Lang::Uk => 3,
+            Lang::En => 2,
+        }
+    }
+
+    // Имена аргументов сообщения и число форм в каждом склонении
+    fn placeholders(text: &str) -> BTreeSet<(String, usize)> {
+        text.split('{')
+            .skip(1)
+            .filter_map(|part| part.split_once('}'))
+            .map(|(inner, _)| {
+                let mut parts = inner.split('|');
+                (parts.next().unwrap_or_default().to_string(), parts.count())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn catalogs_match_russian() {
+        let russian = &CATALOGS[&Lang::Ru];
+        for lang in Lang::ALL {
+            let catalog = &CATALOGS[&lang];
+            for (key, text) in russian {
+                let translated = catalog.get(key).unwrap_or_else(|| panic!("{}: нет ключа {}", lang.code(), key));
+                let names = |text| placeholders(text).into_iter().map(|(name, _)| name).collect::<BTreeSet<_>>();
+                assert_eq!(names(text), names(translated), "{}: аргументы {}", lang.code(), key);
+                for (name, forms) in placeholders(translated) {
+                    assert!(forms == 0 || forms == plural_forms(lang), "{}: формы {} в {}", lang.code(), name, key);
+                }
+            }
+            assert_eq!(catalog.len(), russian.len(), "{}: лишние ключи", lang.code());
+        }
+    }
+
+    #[test]
+    fn plural_rules() {
+        let word = |lang, n: i64| render(lang, "{n} {n|сантиметр|сантиметра|сантиметров}", &[("n", &n)]);
+        assert_eq!(word(Lang::Ru, 1), "1 сантиметр");
+        assert_eq!(word(Lang::Ru, 2), "2 сантиметра");
+        assert_eq!(word(Lang::Ru, 5), "5 сантиметров");
+        assert_eq!(word(Lang::Ru, 11), "11 сантиметров");
+        assert_eq!(word(Lang::Ru, 22), "22 сантиметра");
+        assert_eq!(word(Lang::Ru, -21), "-21 сантиметр");
+
+        let english = |n: i64| render(Lang::En, "{n} {n|centimetre|centimetres}", &[("n", &n)]);
+        assert_eq!(english(1), "1 centimetre");
+        assert_eq!(english(0), "0 centimetres");
+
+        // Знак и дробная часть не мешают выбрать форму
+        let text = |lang, value: &str| render(lang, "{n} {n|сантиметр|сантиметра|сантиметров}", &[("n", &value)]);
+        assert_eq!(text(Lang::Ru, "+1"), "+1 сантиметр");
+        assert_eq!(text(Lang::Ru, "6.5"), "6.5 сантиметра");
+        assert_eq!(text(Lang::Ru, "21.0"), "21.0 сантиметра");
+        assert_eq!(render(Lang::En, "{n} {n|centimetre|centimetres}", &[("n", &"1.5")]), "1.5 centimetres");
+    }
+
+    #[test]
+    fn missing_arguments_stay_visible() {
+        assert_eq!(render(Lang::Ru, "{a} и {b}", &[("a", &1)]), "1 и {b}");
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(Lang::from_code("en-US"), Some(Lang::En));
+        assert_eq!(Lang::from_code("UK"), Some(Lang::Uk));
+        assert_eq!(Lang::from_code("de"), None);
+    }
+}
@@ -0,0 +1,247 @@
+//! Анекдоты для /anekdot из нескольких источников. Источник выбирается случайно с учётом веса,
+//! а если он не ответил или всё его уже видели в чате — берётся следующий.
+
+use std::collections::HashSet;
+use async_trait::async_trait;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use scraper::{Html, Selector};
+use crate::http::{FetchError, HttpClient, Source};
+
+#[async_trait]
+pub trait JokeProvider: Send + Sync {
+    /// Имя в `JOKE_WEIGHTS` и в логах
+    fn name(&self) -> &'static str;
+
+    /// Несколько анекдотов за раз, чтобы было из чего выбрать ещё не показанный.
+    async fn fetch(&self, http: &HttpClient) -> Result<Vec<String>, FetchError>;
+}
+
+/// Случайный анекдот с baneks.ru, по одному за запрос.
+pub struct Baneks;
+
+#[async_trait]
+impl JokeProvider for Baneks {
+    fn name(&self) -> &'static str {
+        "baneks"
+    }
+
+    async fn fetch(&self, http: &HttpClient) -> Result<Vec<String>, FetchError> {
+        let body = http.get(Source::Jokes, "https://baneks.ru/random", &[]).await?;
+        scrape_articles(&body)
+    }
+}
+
+/// Страница случайных анекдотов anekdot.ru, на ней их около двадцати.
+pub struct AnekdotRu;
+
+#[async_trait]
+impl JokeProvider for AnekdotRu {
+    fn name(&self) -> &'static str {
+        "anekdot.ru"
+    }
+
+    async fn fetch(&self, http: &HttpClient) -> Result<Vec<String>, FetchError> {
+        let body = http.get(Source::Anekdots, "https://www.anekdot.ru/random/anekdot/", &[]).await?;
+        scrape(&body, ".topicbox .text")
+    }
+}
+
+/// Анекдоты из `content/<язык>/jokes.toml`, работают без сети.
+pub struct Offline(pub Vec<String>);
+
+#[async_trait]
+impl JokeProvider for Offline {
+    fn name(&self) -> &'static str {
+        "offline"
+    }
+
+    async fn fetch(&self, _http: &HttpClient) -> Result<Vec<String>, FetchError> {
+        Ok(self.0.clone())
+    }
+}
+
+/// Веса по умолчанию: сайты в приоритете, запасные анекдоты — когда сайты не ответили.
+pub const DEFAULT_WEIGHTS: [(&str, u32); 3] = [("baneks", 3), ("anekdot.ru", 2), ("offline", 1)];
+
+// Текст каждого найденного элемента; строки внутри разделены <br>
+fn scrape(body: &str, selector: &str) -> Result<Vec<String>, FetchError> {
+    let document = Html::parse_document(body);
+    let selector = Selector::parse(selector).unwrap();
+    let jokes: Vec<String> = document
+        .select(&selector)
+        .map(|element| element.text().map(str::trim).filter(|line| !line.is_empty()).collect::<Vec<_>>().join("\n"))
+        .filter(|joke| !joke.is_empty())
+        .collect();
+    if jokes.is_empty() {
+        return Err(FetchError::Invalid("на странице нет анекдотов".to_string()));
+    }
+    Ok(jokes)
+}
+
+// Анекдот baneks.ru — все абзацы одной статьи, по строке на абзац
+fn scrape_articles(body: &str) -> Result<Vec<String>, FetchError> {
+    let document = Html::parse_document(body);
+    let (article, paragraph) = (Selector::parse("article").unwrap(), Selector::parse("p").unwrap());
+    let jokes: Vec<String> = document
+        .select(&article)
+        .map(|article| {
+            article
+                .select(&paragraph)
+                .flat_map(|p| p.text())
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })
+        .filter(|joke| !joke.is_empty())
+        .collect();
+    if jokes.is_empty() {
+        return Err(FetchError::Invalid("на странице нет анекдотов".to_string()));
+    }
+    Ok(jokes)
+}
+
+/// Отпечаток анекдота для проверки повторов: не зависит от регистра и пробелов и не меняется между версиями бота.
+pub fn fingerprint(joke: &str) -> String {
+    // FNV-1a: стандартный хешер не обещает одинаковый результат в разных версиях Rust
+    let normalized = joke.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase();
+    let hash = normalized.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3));
+    format!("{:016x}", hash)
+}
+
+pub struct Joke {
+    pub text: String,
+    pub key: String,
+}
+
+impl Joke {
+    fn new(text: String) -> Self {
+        Joke { key: fingerprint(&text), text }
+    }
+}
+
+/// Порядок опроса источников: случайный, но тяжёлые чаще оказываются впереди.
+fn weighted_order(weights: &[u32], rng: &mut impl Rng) -> Vec<usize> {
+    let mut left: Vec<usize> = (0..weights.len()).filter(|&i| weights[i] > 0).collect();
+    let mut order = Vec::with_capacity(left.len());
+    while !left.is_empty() {
+        let total: u32 = left.iter().map(|&i| weights[i]).sum();
+        let mut roll = rng.gen_range(0..total);
+        let pos = left.iter().position(|&i| {
+            if roll < weights[i] {
+                return true;
+            }
+            roll -= weights[i];
+            false
+        }).unwrap();
+        order.push(left.remove(pos));
+    }
+    order
+}
+
+/// Выбирает анекдот, которого не было среди `seen`. Если новых нет нигде, повторяет уже показанный:
+/// это лучше, чем ничего. Ошибка — только если не ответил ни один источник.
+pub async fn pick(providers: &[(Box<dyn JokeProvider>, u32)], http: &HttpClient, seen: &HashSet<String>) -> Result<Joke, FetchError> {
+    let weights: Vec<u32> = providers.iter().map(|(_, weight)| *weight).collect();
+    let order = weighted_order(&weights, &mut rand::thread_rng());
+
+    let mut repeat = None;
+    let mut last_error = FetchError::NotConfigured("JOKE_WEIGHTS");
+    for i in order {
+        let provider = &providers[i].0;
+        let jokes = match provider.fetch(http).await {
+            Ok(jokes) => jokes,
+            Err(e) => {
+                log::warn!("Анекдоты {}: {}", provider.name(), e);
+                last_error = e;
+                continue;
+            }
+        };
+        let (mut fresh, mut old): (Vec<Joke>, Vec<Joke>) = jokes.into_iter().map(Joke::new).partition(|joke| !seen.contains(&joke.key));
+        let mut rng = rand::thread_rng();
+        fresh.shuffle(&mut rng);
+        if let Some(joke) = fresh.pop() {
+            return Ok(joke);
+        }
+        if repeat.is_none() {
+            old.shuffle(&mut rng);
+            repeat = old.pop();
+        }
+        log::info!("Анекдоты {}: всё уже показано в этом чате", provider.name());
+    }
+    repeat.ok_or(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    struct Failing;
+
+    #[async_trait]
+    impl JokeProvider for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn fetch(&self, _http: &HttpClient) -> Result<Vec<String>, FetchError> {
+            Err(FetchError::Status(reqwest::StatusCode::BAD_GATEWAY))
+        }
+    }
+
+    fn offline(jokes: &[&str]) -> Box<dyn JokeProvider> {
+        Box::new(Offline(jokes.iter().map(|joke| joke.to_string()).collect()))
+    }
+
+    fn http() -> HttpClient {
+        HttpClient::new(Default::default())
+    }
+
+    #[tokio::test]
+    async fn falls_back_and_skips_seen_jokes() {
+        let providers = vec![(Box::new(Failing) as Box<dyn JokeProvider>, 100), (offline(&["первый", "второй"]), 1)];
+        let seen = HashSet::from([fingerprint("первый")]);
+        for _ in 0..10 {
+            assert_eq!(pick(&providers, &http(), &seen).await.unwrap().text, "второй");
+        }
+    }
+
+    #[tokio::test]
+    async fn repeats_when_everything_was_seen() {
+        let providers = vec![(offline(&["единственный"]), 1)];
+        let seen = HashSet::from([fingerprint("единственный")]);
+        assert_eq!(pick(&providers, &http(), &seen).await.unwrap().text, "единственный");
+
+        let providers = vec![(Box::new(Failing) as Box<dyn JokeProvider>, 1)];
+        assert!(matches!(pick(&providers, &http(), &seen).await, Err(FetchError::Status(_))));
+    }
+
+    #[test]
+    fn heavier_providers_usually_go_first() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let firsts = (0..1000).filter(|_| weighted_order(&[9, 1, 0], &mut rng)[0] == 0).count();
+        assert!((850..=950).contains(&firsts), "{}", firsts);
+        // Источник с нулевым весом выключен
+        assert_eq!(weighted_order(&[9, 1, 0], &mut rng).len(), 2);
+    }
+
+    #[test]
+    fn scrapes_jokes_and_fingerprints_ignore_spacing() {
+        let page = r#"<div class="topicbox"><div class="text">Штирлиц шёл по лесу.<br>Навстречу ему шёл лес.</div></div>
+                      <div class="topicbox"><div class="text">Второй</div></div>"#;
+        let jokes = scrape(page, ".topicbox .text").unwrap();
+        assert_eq!(jokes, ["Штирлиц шёл по лесу.\nНавстречу ему шёл лес.", "Второй"]);
+        assert!(matches!(scrape("<html></html>", ".topicbox .text"), Err(FetchError::Invalid(_))));
+
+        // На baneks.ru анекдот из нескольких абзацев остаётся одним анекдотом
+        let page = r#"<article><h2>Анекдот #42</h2><p>— Доктор, я умру?</p><p>— Обязательно.<br>Но не сегодня.</p></article>"#;
+        assert_eq!(scrape_articles(page).unwrap(), ["— Доктор, я умру?\n— Обязательно.\nНо не сегодня."]);
+        assert!(matches!(scrape_articles("<html></html>"), Err(FetchError::Invalid(_))));
+
+        assert_eq!(fingerprint("Штирлиц  шёл\nпо лесу"), fingerprint("штирлиц шёл по лесу"));
+        assert_ne!(fingerprint("раз"), fingerprint("два"));
+    }
+}
@@ -1,5 +1,18 @@
+pub(crate) mod chart;
 pub(crate) mod commander;
 pub mod loader;
 pub(crate) mod config;
+pub(crate) mod content;
+pub(crate) mod cooldown;
+pub(crate) mod duel;
+pub(crate) mod error;
+pub(crate) mod http;
+pub(crate) mod i18n;
+pub(crate) mod jokes;
+pub(crate) mod memes;
+pub(crate) mod movies;
+pub(crate) mod season;
 pub mod models;
+pub(crate) mod storage;
+pub(crate) mod weather;
 
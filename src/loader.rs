@@ -1,31 +1,69 @@
-
+use std::sync::Arc;
 use teloxide::dptree;
+use teloxide::Bot;
 use teloxide::dispatching::{Dispatcher, HandlerExt, UpdateFilterExt};
-use teloxide::prelude::Requester;
-use teloxide::types::Update;
-use teloxide::utils::command::BotCommands;
-use crate::commander::{Command, command_handler};
-use crate::config::Config;
+use teloxide::error_handlers::LoggingErrorHandler;
+use teloxide::payloads::SetMyCommandsSetters;
+use teloxide::prelude::{Message, Requester};
+use teloxide::types::{CallbackQuery, Poll, Update};
+use tokio::sync::Mutex;
+use crate::commander::{Command, callback_handler, command_handler, localized_commands, poll_handler};
+use crate::config::{Config, SharedConfig};
+use crate::error::{Error, ErrorContext, report};
+use crate::http::HttpClient;
+use crate::i18n::Lang;
 
-pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub async fn run() -> Result<(), Error> {
 
-    let config = Config::new().await;
+    let config = Config::new().await?;
+    let bot = config.get_bot().clone();
 
-    let command_menu = Command::bot_commands();
-    config.get_bot().set_my_commands(command_menu.clone()).await?;
+    // Telegram показывает меню на языке клиента; без кода языка — для всех остальных
+    for lang in Lang::ALL {
+        bot.set_my_commands(localized_commands(lang)).language_code(lang.code()).await?;
+    }
+    bot.set_my_commands(localized_commands(config.get_default_language())).await?;
 
+    // Ошибки обработчиков уходят в report: он пишет их в лог с чатом, пользователем и командой
+    // и извиняется перед пользователем
     let commadn_handler = Update::filter_message()
         .filter_command::<Command>()
-        .endpoint(command_handler);
+        .endpoint(|bot: Bot, msg: Message, cmd: Command, config: SharedConfig, http: HttpClient| async move {
+            let context = ErrorContext::message(&msg);
+            let result = command_handler(bot.clone(), msg, cmd, config.clone(), http).await;
+            report(&bot, &config, context, result).await
+        });
+
+    let callback_handler = Update::filter_callback_query()
+        .endpoint(|bot: Bot, q: CallbackQuery, config: SharedConfig, http: HttpClient| async move {
+            let context = ErrorContext::callback(&q);
+            let result = callback_handler(bot.clone(), q, config.clone(), http).await;
+            report(&bot, &config, context, result).await
+        });
+
+    // Закрытие опросов /movienight
+    let poll_handler = Update::filter_poll()
+        .endpoint(|bot: Bot, poll: Poll, config: SharedConfig| async move {
+            let context = ErrorContext::poll(&poll);
+            let result = poll_handler(bot.clone(), poll, config.clone()).await;
+            report(&bot, &config, context, result).await
+        });
 
     let handlers = dptree::entry()
-        .branch(commadn_handler);
+        .branch(commadn_handler)
+        .branch(callback_handler)
+        .branch(poll_handler);
+
+    // Одно состояние на весь процесс, обработчики получают его через зависимости
+    let http = config.get_http().clone();
+    let config = Arc::new(Mutex::new(config));
 
-    Dispatcher::builder(config.get_bot().clone(), handlers)
+    Dispatcher::builder(bot, handlers)
+        .dependencies(dptree::deps![config, http])
+        .error_handler(LoggingErrorHandler::with_custom_text("Не удалось извиниться за ошибку"))
         .enable_ctrlc_handler()
         .build()
         .dispatch()
         .await;
     Ok(())
-}
\ No newline at end of file
+}
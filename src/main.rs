@@ -1,7 +1,20 @@
+mod chart;
 mod commander;
 mod loader;
 mod config;
+mod content;
+mod cooldown;
+mod duel;
+mod error;
+mod http;
+mod i18n;
+mod jokes;
+mod memes;
+mod movies;
+mod season;
 mod models;
+mod storage;
+mod weather;
 
 use crate::loader::run;
 
@@ -11,6 +24,9 @@ async fn main() {
     pretty_env_logger::init();
     log::info!("Bot started");
 
-    run().await.expect("TODO: panic message");
+    if let Err(e) = run().await {
+        log::error!("Бот остановлен: {}", e);
+        std::process::exit(1);
+    }
 }
 
@@ -0,0 +1,264 @@
+//! Мемы для /meme из нескольких источников. Каждый источник сначала берёт мемы с основной страницы,
+//! а если она не ответила — с запасной. Перед отправкой у файла проверяется тип: по ссылке Telegram
+//! показывает только картинки, GIF и MP4.
+
+use async_trait::async_trait;
+use rand::seq::SliceRandom;
+use reqwest::Url;
+use scraper::{Html, Selector};
+use serde::Deserialize;
+use crate::http::{FetchError, HttpClient, Source};
+use crate::i18n::Lang;
+
+/// Сколько адресов одного источника проверять, прежде чем перейти к следующему.
+const CHECKS_PER_PROVIDER: usize = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemeKind {
+    Photo,
+    /// GIF, отправляется через send_animation
+    Animation,
+    /// MP4, отправляется через send_video
+    Video,
+}
+
+impl MemeKind {
+    /// Как отправлять файл с таким Content-Type; None — Telegram его не покажет.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+        match mime.as_str() {
+            "image/jpeg" | "image/jpg" | "image/png" | "image/webp" => Some(MemeKind::Photo),
+            "image/gif" => Some(MemeKind::Animation),
+            "video/mp4" => Some(MemeKind::Video),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Meme {
+    pub url: Url,
+    pub kind: MemeKind,
+}
+
+#[async_trait]
+pub trait MemeProvider: Send + Sync {
+    /// Имя для логов
+    fn name(&self) -> &'static str;
+
+    /// Абсолютные адреса мемов: с основной страницы, а при её ошибке — с запасной.
+    async fn fetch(&self, http: &HttpClient) -> Result<Vec<Url>, FetchError>;
+}
+
+/// Мемы anekdot.ru: случайные, а если эта страница не ответила — последние.
+pub struct AnekdotRu;
+
+impl AnekdotRu {
+    const RANDOM: (&'static str, &'static str) = ("https://pda.anekdot.ru/random/mem/", ".content img, .content video source");
+    const LAST: (&'static str, &'static str) = ("https://www.anekdot.ru/last/mem/", ".topicbox img, .topicbox video source");
+
+    async fn page(&self, http: &HttpClient, (url, selector): (&str, &str)) -> Result<Vec<Url>, FetchError> {
+        let body = http.get(Source::Memes, url, &[]).await?;
+        scrape(&body, &Url::parse(url).unwrap(), selector)
+    }
+}
+
+#[async_trait]
+impl MemeProvider for AnekdotRu {
+    fn name(&self) -> &'static str {
+        "anekdot.ru"
+    }
+
+    async fn fetch(&self, http: &HttpClient) -> Result<Vec<Url>, FetchError> {
+        match self.page(http, Self::RANDOM).await {
+            Ok(urls) => Ok(urls),
+            Err(e) => {
+                log::warn!("Мемы {}: {}, берём последние", self.name(), e);
+                self.page(http, Self::LAST).await
+            }
+        }
+    }
+}
+
+/// Мемы с Reddit через meme-api.com: из своего сабреддита, а при ошибке — из любых мемных.
+pub struct MemeApi {
+    pub subreddit: &'static str,
+}
+
+impl MemeApi {
+    async fn page(&self, http: &HttpClient, url: &str) -> Result<Vec<Url>, FetchError> {
+        let body = http.get(Source::MemeApi, url, &[]).await?;
+        parse_meme_api(&body)
+    }
+}
+
+#[async_trait]
+impl MemeProvider for MemeApi {
+    fn name(&self) -> &'static str {
+        "meme-api.com"
+    }
+
+    async fn fetch(&self, http: &HttpClient) -> Result<Vec<Url>, FetchError> {
+        match self.page(http, &format!("https://meme-api.com/gimme/{}/10", self.subreddit)).await {
+            Ok(urls) => Ok(urls),
+            Err(e) => {
+                log::warn!("Мемы {} r/{}: {}, берём из любых сабреддитов", self.name(), self.subreddit, e);
+                self.page(http, "https://meme-api.com/gimme/10").await
+            }
+        }
+    }
+}
+
+/// Источники по порядку опроса: русскоязычные чаты начинают с anekdot.ru, англоязычные — с Reddit.
+pub fn providers(lang: Lang) -> Vec<Box<dyn MemeProvider>> {
+    let anekdot: Box<dyn MemeProvider> = Box::new(AnekdotRu);
+    let reddit: Box<dyn MemeProvider> = Box::new(MemeApi { subreddit: "memes" });
+    match lang {
+        Lang::En => vec![reddit, anekdot],
+        Lang::Ru | Lang::Uk => vec![anekdot, reddit],
+    }
+}
+
+// Адреса из src; относительные и без схемы («//cdn...») считаются от адреса страницы
+fn scrape(body: &str, page: &Url, selector: &str) -> Result<Vec<Url>, FetchError> {
+    let document = Html::parse_document(body);
+    let selector = Selector::parse(selector).unwrap();
+    let urls: Vec<Url> = document
+        .select(&selector)
+        .filter_map(|element| element.value().attr("src"))
+        .filter_map(|src| page.join(src.trim()).ok())
+        .filter(|url| matches!(url.scheme(), "http" | "https"))
+        .collect();
+    if urls.is_empty() {
+        return Err(FetchError::Invalid("на странице нет картинок".to_string()));
+    }
+    Ok(urls)
+}
+
+#[derive(Deserialize)]
+struct MemeApiResponse {
+    memes: Vec<MemeApiPost>,
+}
+
+#[derive(Deserialize)]
+struct MemeApiPost {
+    url: String,
+    #[serde(default)]
+    nsfw: bool,
+    #[serde(default)]
+    spoiler: bool,
+}
+
+// Посты 18+ и спойлеры в чат не отправляем
+fn parse_meme_api(body: &str) -> Result<Vec<Url>, FetchError> {
+    let response: MemeApiResponse = serde_json::from_str(body).map_err(|e| FetchError::Invalid(e.to_string()))?;
+    let urls: Vec<Url> = response
+        .memes
+        .into_iter()
+        .filter(|post| !post.nsfw && !post.spoiler)
+        .filter_map(|post| Url::parse(&post.url).ok())
+        .collect();
+    if urls.is_empty() {
+        return Err(FetchError::Invalid("нет подходящих мемов".to_string()));
+    }
+    Ok(urls)
+}
+
+/// Первый мем, который Telegram сможет показать. Источники опрашиваются по порядку, у каждого
+/// проверяются несколько случайных адресов. Файлы лежат на сторонних хостингах, поэтому проверки идут
+/// через отдельный источник [`Source::MemeFiles`] и не закрывают предохранитель самих страниц.
+/// Ошибка — последняя, если не подошло ничего.
+pub async fn pick(providers: &[Box<dyn MemeProvider>], http: &HttpClient) -> Result<Meme, FetchError> {
+    let mut last_error = FetchError::Invalid("нет ни одного источника мемов".to_string());
+    for provider in providers {
+        let mut urls = match provider.fetch(http).await {
+            Ok(urls) => urls,
+            Err(e) => {
+                log::warn!("Мемы {}: {}", provider.name(), e);
+                last_error = e;
+                continue;
+            }
+        };
+        urls.shuffle(&mut rand::thread_rng());
+        for url in urls.into_iter().take(CHECKS_PER_PROVIDER) {
+            match http.content_type(Source::MemeFiles, &url).await {
+                Ok(content_type) => match MemeKind::from_content_type(&content_type) {
+                    Some(kind) => return Ok(Meme { url, kind }),
+                    None => {
+                        log::info!("Мемы {}: {} имеет тип «{}», пропускаем", provider.name(), url, content_type);
+                        last_error = FetchError::Invalid(format!("неподходящий тип файла «{}»", content_type));
+                    }
+                },
+                Err(e) => {
+                    log::warn!("Мемы {}: {}: {}", provider.name(), url, e);
+                    last_error = e;
+                }
+            }
+        }
+    }
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Failing;
+
+    #[async_trait]
+    impl MemeProvider for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        async fn fetch(&self, _http: &HttpClient) -> Result<Vec<Url>, FetchError> {
+            Err(FetchError::Status(reqwest::StatusCode::BAD_GATEWAY))
+        }
+    }
+
+    #[test]
+    fn relative_sources_become_absolute() {
+        let page = Url::parse("https://pda.anekdot.ru/random/mem/").unwrap();
+        let body = r#"<div class="content">
+                        <img src="/i/mem.jpg">
+                        <img src="//cdn.example.com/cat.gif">
+                        <img src="data:image/png;base64,AAAA">
+                        <video><source src="https://video.example.com/clip.mp4"></video>
+                      </div>"#;
+        let urls: Vec<String> = scrape(body, &page, ".content img, .content video source").unwrap().iter().map(Url::to_string).collect();
+        assert_eq!(urls, ["https://pda.anekdot.ru/i/mem.jpg", "https://cdn.example.com/cat.gif", "https://video.example.com/clip.mp4"]);
+
+        // Раньше на такой странице бот падал на разборе адреса
+        assert!(matches!(scrape("<html></html>", &page, ".content img"), Err(FetchError::Invalid(_))));
+    }
+
+    #[test]
+    fn content_type_decides_how_to_send() {
+        assert_eq!(MemeKind::from_content_type("image/jpeg"), Some(MemeKind::Photo));
+        assert_eq!(MemeKind::from_content_type("Image/PNG; charset=binary"), Some(MemeKind::Photo));
+        assert_eq!(MemeKind::from_content_type("image/gif"), Some(MemeKind::Animation));
+        assert_eq!(MemeKind::from_content_type("video/mp4"), Some(MemeKind::Video));
+        assert_eq!(MemeKind::from_content_type("text/html; charset=utf-8"), None);
+        assert_eq!(MemeKind::from_content_type(""), None);
+    }
+
+    #[test]
+    fn meme_api_skips_nsfw_and_spoilers() {
+        let body = r#"{"count": 3, "memes": [
+            {"postLink": "https://redd.it/1", "subreddit": "memes", "title": "ok", "url": "https://i.redd.it/ok.jpg", "nsfw": false, "spoiler": false},
+            {"postLink": "https://redd.it/2", "subreddit": "memes", "title": "18+", "url": "https://i.redd.it/nsfw.jpg", "nsfw": true, "spoiler": false},
+            {"postLink": "https://redd.it/3", "subreddit": "memes", "title": "end", "url": "https://i.redd.it/spoiler.gif", "nsfw": false, "spoiler": true}
+        ]}"#;
+        let urls = parse_meme_api(body).unwrap();
+        assert_eq!(urls, [Url::parse("https://i.redd.it/ok.jpg").unwrap()]);
+
+        assert!(matches!(parse_meme_api(r#"{"code": 429, "message": "slow down"}"#), Err(FetchError::Invalid(_))));
+    }
+
+    #[tokio::test]
+    async fn reports_last_error_when_every_source_fails() {
+        let providers: Vec<Box<dyn MemeProvider>> = vec![Box::new(Failing), Box::new(Failing)];
+        let result = pick(&providers, &HttpClient::new(Default::default())).await;
+        assert!(matches!(result, Err(FetchError::Status(_))));
+    }
+}
@@ -0,0 +1,155 @@
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+use super::ChangeSource;
+
+/// Достижения, которые открываются за изменения размера.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Achievement {
+    FirstCentimetre,
+    LostItAll,
+    Jackpot,
+    TrainingWeek,
+    TopOne,
+}
+
+impl Achievement {
+    /// Все достижения в порядке показа в /achievements.
+    pub const ALL: [Achievement; 5] = [
+        Achievement::FirstCentimetre,
+        Achievement::Jackpot,
+        Achievement::TrainingWeek,
+        Achievement::TopOne,
+        Achievement::LostItAll,
+    ];
+
+    /// Имя для хранения; по нему же в каталогах переводов ищутся название и описание
+    /// (`achievement.<имя>.title` и `.description`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Achievement::FirstCentimetre => "first_centimetre",
+            Achievement::LostItAll => "lost_it_all",
+            Achievement::Jackpot => "jackpot",
+            Achievement::TrainingWeek => "training_week",
+            Achievement::TopOne => "top_one",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|a| a.as_str() == name)
+    }
+
+    pub fn emoji(self) -> &'static str {
+        match self {
+            Achievement::FirstCentimetre => "🌱",
+            Achievement::LostItAll => "💀",
+            Achievement::Jackpot => "🎰",
+            Achievement::TrainingWeek => "🏋️",
+            Achievement::TopOne => "👑",
+        }
+    }
+}
+
+/// Открытое пользователем достижение.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct UnlockedAchievement {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub achievement: Achievement,
+    pub at: DateTime<Utc>,
+}
+
+/// Состояние пользователя после изменения размера, по которому проверяются достижения.
+#[derive(Clone, Copy, Debug)]
+pub struct AchievementCheck {
+    pub source: ChangeSource,
+    pub before: i32,
+    /// Изменение до ограничения нулём
+    pub change: i32,
+    /// Число, выпавшее на замере, до штрафа за прерванную серию; из него же берётся сообщение замера
+    pub roll: Option<i32>,
+    pub after: i32,
+    /// Сколько игровых дней подряд пользователь тренировался, включая текущий
+    pub training_streak: u32,
+    /// Место в рейтинге чата (с единицы) и число участников в нём
+    pub rank: usize,
+    pub chat_size: usize,
+}
+
+/// Достижения, условия которых выполнены. Уже открытые отсеиваются при сохранении.
+pub fn check_achievements(check: &AchievementCheck) -> Vec<Achievement> {
+    Achievement::ALL
+        .into_iter()
+        .filter(|achievement| match achievement {
+            Achievement::FirstCentimetre => check.before == 0 && check.after > 0,
+            Achievement::LostItAll => check.before > 0 && check.before + check.change < 0,
+            Achievement::Jackpot => check.source == ChangeSource::Roll && check.roll.is_some_and(|roll| roll >= 10),
+            Achievement::TrainingWeek => check.training_streak >= 7,
+            Achievement::TopOne => check.rank == 1 && check.chat_size > 1 && check.after > 0,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: ChangeSource, before: i32, change: i32) -> AchievementCheck {
+        AchievementCheck {
+            source,
+            before,
+            change,
+            roll: (source == ChangeSource::Roll).then_some(change),
+            after: (before + change).max(0),
+            training_streak: 0,
+            rank: 2,
+            chat_size: 2,
+        }
+    }
+
+    #[test]
+    fn first_roll_jackpot() {
+        let unlocked = check_achievements(&check(ChangeSource::Roll, 0, 10));
+        assert_eq!(unlocked, [Achievement::FirstCentimetre, Achievement::Jackpot]);
+
+        // Десятка за тренировку джекпотом не считается
+        assert!(check_achievements(&check(ChangeSource::Training, 5, 10)).is_empty());
+    }
+
+    #[test]
+    fn jackpot_counts_roll_before_streak_penalty() {
+        // Выпало +10, но за прерванную серию сняли 3: сообщение о десятке и джекпот должны совпадать
+        let penalized = AchievementCheck { roll: Some(10), ..check(ChangeSource::Roll, 5, 7) };
+        assert_eq!(check_achievements(&penalized), [Achievement::Jackpot]);
+
+        let net_ten = AchievementCheck { roll: Some(9), ..check(ChangeSource::Roll, 5, 10) };
+        assert!(check_achievements(&net_ten).is_empty());
+    }
+
+    #[test]
+    fn lost_it_all_only_when_clamped() {
+        assert_eq!(check_achievements(&check(ChangeSource::Roll, 3, -7)), [Achievement::LostItAll]);
+        assert!(check_achievements(&check(ChangeSource::Roll, 7, -7)).is_empty());
+    }
+
+    #[test]
+    fn streak_and_rank() {
+        let state = AchievementCheck {
+            training_streak: 7,
+            rank: 1,
+            ..check(ChangeSource::Training, 5, 1)
+        };
+        assert_eq!(check_achievements(&state), [Achievement::TrainingWeek, Achievement::TopOne]);
+
+        // Единственный участник чата не становится царём горы
+        let alone = AchievementCheck { rank: 1, chat_size: 1, ..check(ChangeSource::Roll, 5, 1) };
+        assert!(check_achievements(&alone).is_empty());
+    }
+
+    #[test]
+    fn names_round_trip() {
+        for achievement in Achievement::ALL {
+            assert_eq!(Achievement::from_name(achievement.as_str()), Some(achievement));
+        }
+    }
+}
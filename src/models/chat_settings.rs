@@ -0,0 +1,38 @@
+use serde::{Serialize, Deserialize};
+use chrono::NaiveDate;
+
+/// Настройки чата. Незаданные поля берутся из значений по умолчанию в конфигурации.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ChatSettings {
+    pub chat_id: i64,
+    /// Часовой пояс IANA, например `Asia/Omsk`
+    pub timezone: Option<String>,
+    /// Час (0–23) по местному времени, в который обновляются дневные лимиты
+    pub reset_hour: Option<u32>,
+    /// Первый игровой день текущего сезона; пусто, пока чат не застал ни одного сезона
+    pub season_start: Option<NaiveDate>,
+    /// Код языка ответов бота (`ru`, `uk`, `en`)
+    pub language: Option<String>,
+    /// Город для /weather, выбранный через /setcity
+    pub location: Option<Location>,
+}
+
+/// Место, для которого показывается погода.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Location {
+    /// Название для показа, например «Берлин, Германия»
+    pub name: String,
+    pub latitude: f64,
+    pub longitude: f64,
+    /// Часовой пояс IANA, в котором показывается прогноз
+    pub timezone: String,
+}
+
+impl ChatSettings {
+    pub fn new(chat_id: i64) -> Self {
+        Self {
+            chat_id,
+            ..Self::default()
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+
+/// Откуда пришло изменение размера.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeSource {
+    Roll,
+    Training,
+    Weather,
+    Duel,
+    /// Обнуление в конце сезона
+    Season,
+}
+
+impl ChangeSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeSource::Roll => "roll",
+            ChangeSource::Training => "training",
+            ChangeSource::Weather => "weather",
+            ChangeSource::Duel => "duel",
+            ChangeSource::Season => "season",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "roll" => Some(ChangeSource::Roll),
+            "training" => Some(ChangeSource::Training),
+            "weather" => Some(ChangeSource::Weather),
+            "duel" => Some(ChangeSource::Duel),
+            "season" => Some(ChangeSource::Season),
+            _ => None,
+        }
+    }
+}
+
+/// Одно изменение размера пользователя в чате.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SizeEvent {
+    pub chat_id: i64,
+    pub user_id: i64,
+    pub at: DateTime<Utc>,
+    pub source: ChangeSource,
+    /// Фактическое изменение с учётом того, что размер не уходит ниже нуля
+    pub delta: i32,
+    /// Размер после изменения
+    pub size: i32,
+}
+
+impl SizeEvent {
+    pub fn new(chat_id: i64, user_id: i64, source: ChangeSource, before: i32, after: i32) -> Self {
+        Self {
+            chat_id,
+            user_id,
+            at: Utc::now(),
+            source,
+            delta: after - before,
+            size: after,
+        }
+    }
+}
+
+/// Сводка по истории пользователя.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct HistorySummary {
+    pub best_day: Option<(NaiveDate, i32)>,
+    pub worst_day: Option<(NaiveDate, i32)>,
+    /// Самая длинная серия изменений подряд в плюс
+    pub longest_growth: usize,
+    /// Самая длинная серия изменений подряд в минус
+    pub longest_decline: usize,
+    /// Текущая серия: положительная — рост, отрицательная — падение
+    pub current_streak: i32,
+}
+
+impl SizeEvent {
+    /// Обнуление в конце сезона: не заслуга и не потеря игрока, в сводки и графики не попадает.
+    pub fn is_season_reset(&self) -> bool {
+        self.source == ChangeSource::Season
+    }
+}
+
+/// Считает лучший и худший день и серии по событиям в хронологическом порядке.
+/// Дни считаются в часовом поясе `tz`, обнуления сезона не учитываются.
+pub fn summarize<Tz: TimeZone>(events: &[SizeEvent], tz: &Tz) -> HistorySummary {
+    let events: Vec<&SizeEvent> = events.iter().filter(|e| !e.is_season_reset()).collect();
+    let mut days: BTreeMap<NaiveDate, i32> = BTreeMap::new();
+    for event in &events {
+        *days.entry(event.at.with_timezone(tz).date_naive()).or_default() += event.delta;
+    }
+
+    let mut summary = HistorySummary {
+        best_day: days.iter().max_by_key(|(_, delta)| **delta).map(|(d, v)| (*d, *v)),
+        worst_day: days.iter().min_by_key(|(_, delta)| **delta).map(|(d, v)| (*d, *v)),
+        ..HistorySummary::default()
+    };
+
+    // Нулевые изменения не прерывают серию, но и не продолжают её
+    for event in events.iter().filter(|e| e.delta != 0) {
+        summary.current_streak = match (event.delta > 0, summary.current_streak) {
+            (true, streak) if streak > 0 => streak + 1,
+            (true, _) => 1,
+            (false, streak) if streak < 0 => streak - 1,
+            (false, _) => -1,
+        };
+        if summary.current_streak > 0 {
+            summary.longest_growth = summary.longest_growth.max(summary.current_streak as usize);
+        } else {
+            summary.longest_decline = summary.longest_decline.max(summary.current_streak.unsigned_abs() as usize);
+        }
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(day: u32, hour: u32, delta: i32) -> SizeEvent {
+        SizeEvent {
+            chat_id: 1,
+            user_id: 1,
+            at: Utc.with_ymd_and_hms(2024, 11, day, hour, 0, 0).unwrap(),
+            source: ChangeSource::Roll,
+            delta,
+            size: 0,
+        }
+    }
+
+    #[test]
+    fn summarizes_days_and_streaks() {
+        let events = [
+            event(1, 10, 5),
+            event(1, 12, -1),
+            event(2, 10, -3),
+            event(3, 10, 0),
+            event(3, 11, -2),
+            event(4, 10, 2),
+            event(5, 10, 1),
+        ];
+
+        let summary = summarize(&events, &Utc);
+
+        assert_eq!(summary.best_day, Some((NaiveDate::from_ymd_opt(2024, 11, 1).unwrap(), 4)));
+        assert_eq!(summary.worst_day, Some((NaiveDate::from_ymd_opt(2024, 11, 2).unwrap(), -3)));
+        assert_eq!(summary.longest_decline, 3);
+        assert_eq!(summary.longest_growth, 2);
+        assert_eq!(summary.current_streak, 2);
+    }
+
+    #[test]
+    fn season_reset_does_not_change_summary() {
+        let events = [event(1, 10, 5), event(2, 10, 3), event(4, 10, 2)];
+        let reset = SizeEvent { source: ChangeSource::Season, ..event(3, 0, -8) };
+        let with_reset = [events[0].clone(), events[1].clone(), reset, events[2].clone()];
+
+        assert_eq!(summarize(&with_reset, &Utc), summarize(&events, &Utc));
+        assert_eq!(summarize(&with_reset, &Utc).current_streak, 3);
+    }
+
+    #[test]
+    fn empty_history() {
+        assert_eq!(summarize(&[], &Utc), HistorySummary::default());
+    }
+}
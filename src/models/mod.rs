@@ -1,5 +1,19 @@
 mod user_data;
 mod training;
+mod history;
+mod chat_settings;
+mod achievement;
+mod streak;
+mod season;
+mod seen;
+mod watchlist;
 
 pub use user_data::*;
-pub use training::*;
\ No newline at end of file
+pub use training::*;
+pub use history::*;
+pub use chat_settings::*;
+pub use achievement::*;
+pub use streak::*;
+pub use season::*;
+pub use seen::*;
+pub use watchlist::*;
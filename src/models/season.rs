@@ -0,0 +1,28 @@
+use serde::{Serialize, Deserialize};
+use chrono::NaiveDate;
+
+/// Место участника в итоговой таблице сезона.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeasonStanding {
+    pub user_id: i64,
+    /// Имя на момент окончания сезона
+    pub name: String,
+    pub pisun: i32,
+}
+
+/// Итоги завершённого сезона в чате. Таблица отсортирована от первого места.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeasonResult {
+    pub chat_id: i64,
+    /// Первый игровой день сезона
+    pub start: NaiveDate,
+    /// Первый игровой день следующего сезона
+    pub end: NaiveDate,
+    pub standings: Vec<SeasonStanding>,
+}
+
+impl SeasonResult {
+    pub fn winner(&self) -> Option<&SeasonStanding> {
+        self.standings.first()
+    }
+}
@@ -0,0 +1,28 @@
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+
+/// Что бот показывает в чате и старается не повторять.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SeenKind {
+    Joke,
+    Movie,
+}
+
+impl SeenKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SeenKind::Joke => "joke",
+            SeenKind::Movie => "movie",
+        }
+    }
+}
+
+/// Показ в чате. `key` — отпечаток содержимого (у фильмов — id в TMDB), а не оно само.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SeenItem {
+    pub chat_id: i64,
+    pub kind: SeenKind,
+    pub key: String,
+    pub at: DateTime<Utc>,
+}
@@ -0,0 +1,76 @@
+use serde::{Serialize, Deserialize};
+
+/// Вехи серии: с какого дня подряд и насколько поднимается нижняя граница замера.
+pub const STREAK_MILESTONES: [(u32, i32); 4] = [(3, 1), (7, 3), (14, 5), (30, 7)];
+
+/// Серия дней подряд, в которые пользователь вызывал команду.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Streak {
+    pub current: u32,
+    pub best: u32,
+}
+
+impl Streak {
+    /// Засчитывает новый игровой день. `days_since_last` — сколько игровых дней прошло
+    /// с прошлого вызова. Если серия прервалась, возвращает её прежнюю длину.
+    pub fn advance(&mut self, days_since_last: i64) -> Option<u32> {
+        let broken = (days_since_last > 1 && self.current > 0).then_some(self.current);
+        self.current = match days_since_last {
+            // Повторный вызов в тот же день серию не меняет
+            0 => self.current.max(1),
+            1 => self.current + 1,
+            _ => 1,
+        };
+        self.best = self.best.max(self.current);
+        broken
+    }
+
+    /// Текущая длина серии на момент показа: если день уже пропущен, серии больше нет.
+    pub fn alive(&self, days_since_last: i64) -> u32 {
+        if days_since_last <= 1 { self.current } else { 0 }
+    }
+}
+
+/// Бонус к нижней границе замера за серию такой длины.
+pub fn streak_bonus(days: u32) -> i32 {
+    STREAK_MILESTONES
+        .iter()
+        .rev()
+        .find(|(from, _)| days >= *from)
+        .map_or(0, |(_, bonus)| *bonus)
+}
+
+/// Является ли длина серии вехой, на которой растёт бонус.
+pub fn is_milestone(days: u32) -> bool {
+    STREAK_MILESTONES.iter().any(|(from, _)| *from == days)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grows_on_consecutive_days() {
+        let mut streak = Streak::default();
+        assert_eq!(streak.advance(1), None);
+        assert_eq!(streak.advance(1), None);
+        assert_eq!(streak, Streak { current: 2, best: 2 });
+    }
+
+    #[test]
+    fn breaks_after_missed_day() {
+        let mut streak = Streak { current: 5, best: 8 };
+        assert_eq!(streak.advance(2), Some(5));
+        assert_eq!(streak, Streak { current: 1, best: 8 });
+        assert_eq!(streak.alive(3), 0);
+    }
+
+    #[test]
+    fn bonus_by_milestones() {
+        assert_eq!(streak_bonus(2), 0);
+        assert_eq!(streak_bonus(3), 1);
+        assert_eq!(streak_bonus(13), 3);
+        assert_eq!(streak_bonus(100), 7);
+        assert!(is_milestone(7) && !is_milestone(8));
+    }
+}
@@ -1,22 +1,75 @@
 use serde::{Serialize, Deserialize};
 use chrono::{DateTime, Utc, Duration};
+use teloxide::types::User;
+use super::Streak;
 
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct UserData {
+    // В старых файлах поля не было, чат проставляется при миграции
+    #[serde(default)]
+    pub chat_id: i64,
     pub user_id: i64,
     pub pisun: i32,
     pub last_command: DateTime<Utc>,
     pub last_train: DateTime<Utc>,
+    pub username: Option<String>,
+    pub first_name: Option<String>,
+    pub last_name: Option<String>,
+    /// Последняя дуэль, для отката между дуэлями
+    pub last_duel: Option<DateTime<Utc>>,
+    /// Дни подряд с замером
+    pub roll_streak: Streak,
+    /// Дни подряд с тренировкой
+    pub train_streak: Streak,
 }
 
 impl UserData {
-    pub fn new(user_id: i64) -> Self {
+    pub fn new(chat_id: i64, user_id: i64) -> Self {
         Self {
+            chat_id,
             user_id,
             pisun: 0,
             last_command: Utc::now() - Duration::days(1),
             last_train: Utc::now() - Duration::days(1),
+            username: None,
+            first_name: None,
+            last_name: None,
+            last_duel: None,
+            roll_streak: Streak::default(),
+            train_streak: Streak::default(),
         }
     }
-}
\ No newline at end of file
+
+    /// Обновляет имя из профиля Telegram. Возвращает `true`, если что-то поменялось.
+    pub fn set_profile(&mut self, user: &User) -> bool {
+        let username = user.username.clone();
+        let first_name = Some(user.first_name.clone()).filter(|name| !name.is_empty());
+        let last_name = user.last_name.clone();
+
+        let changed = self.username != username
+            || self.first_name != first_name
+            || self.last_name != last_name;
+        self.username = username;
+        self.first_name = first_name;
+        self.last_name = last_name;
+        changed
+    }
+
+    /// Имя для показа в чате: имя и фамилия, иначе @username, иначе заглушка.
+    pub fn display_name(&self) -> String {
+        let full_name = [self.first_name.as_deref(), self.last_name.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        if !full_name.is_empty() {
+            full_name
+        } else if let Some(username) = &self.username {
+            format!("@{}", username)
+        } else {
+            format!("Аноним #{}", self.user_id)
+        }
+    }
+}
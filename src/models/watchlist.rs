@@ -0,0 +1,24 @@
+use serde::{Serialize, Deserialize};
+use chrono::{DateTime, Utc};
+
+/// Фильм, который чат собирается посмотреть.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WatchlistEntry {
+    pub chat_id: i64,
+    /// id фильма в TMDB
+    pub movie_id: u64,
+    pub title: String,
+    pub added_by: i64,
+    pub added_at: DateTime<Utc>,
+}
+
+/// Голосование за фильм вечера, пока Telegram не закрыл опрос.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MoviePoll {
+    pub poll_id: String,
+    pub chat_id: i64,
+    pub message_id: i32,
+    /// Фильмы в порядке вариантов опроса
+    pub movie_ids: Vec<u64>,
+    pub started_at: DateTime<Utc>,
+}
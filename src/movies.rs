@@ -0,0 +1,338 @@
+//! Фильмы из TMDB для /randommovie: подбор через discover по жанру, годам, рейтингу и языку оригинала.
+//! Страница выдачи выбирается случайно. Ответы TMDB, включая список жанров, общий клиент кэширует на сутки.
+
+use std::collections::HashSet;
+use std::fmt;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use reqwest::Url;
+use serde::Deserialize;
+use crate::http::{FetchError, HttpClient, Source};
+use crate::i18n::Lang;
+
+const API_URL: &str = "https://api.themoviedb.org/3";
+
+/// Дальше 500-й страницы discover не отдаёт.
+const MAX_PAGE: u32 = 500;
+
+/// Сколько случайных страниц просмотреть в поисках ещё не предложенного фильма.
+const PAGE_ATTEMPTS: usize = 3;
+
+/// Фильмы с меньшим числом оценок пропускаются: у них рейтинг 10 из 10 от двух зрителей.
+const MIN_VOTES: &str = "50";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Genre {
+    pub id: u32,
+    pub name: String,
+}
+
+/// Условия подбора. Пустой фильтр — любой популярный фильм.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MovieFilter {
+    pub genre: Option<u32>,
+    /// Годы выхода включительно
+    pub years: Option<(i32, i32)>,
+    pub min_rating: Option<f64>,
+    /// Язык оригинала, код ISO 639-1
+    pub original_language: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    UnknownGenre(String),
+    /// Число не похоже ни на год, ни на рейтинг от 0 до 10
+    BadNumber(String),
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::UnknownGenre(genre) => write!(f, "нет жанра «{}»", genre),
+            FilterError::BadNumber(value) => write!(f, "«{}» — не год и не рейтинг", value),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+impl MovieFilter {
+    /// Разбирает аргументы команды в любом порядке: `комедия 1990-2000 7.5 fr`. Четыре цифры — год,
+    /// число до 10 — наименьший рейтинг, две латинские буквы — язык оригинала, остальное — жанр.
+    pub fn parse(args: &str, genres: &[Genre]) -> Result<Self, FilterError> {
+        let mut filter = MovieFilter::default();
+        let mut genre_words = Vec::new();
+        for word in args.split_whitespace() {
+            if let Some(years) = parse_years(word) {
+                filter.years = Some(years);
+            } else if word.chars().all(|c| c.is_ascii_digit() || c == '.' || c == ',') {
+                match word.replace(',', ".").parse::<f64>() {
+                    Ok(rating) if (0.0..=10.0).contains(&rating) => filter.min_rating = Some(rating),
+                    _ => return Err(FilterError::BadNumber(word.to_string())),
+                }
+            } else if word.len() == 2 && word.chars().all(|c| c.is_ascii_alphabetic()) {
+                // TMDB ждёт код в нижнем регистре, а пишут и FR, и En
+                filter.original_language = Some(word.to_ascii_lowercase());
+            } else {
+                genre_words.push(word);
+            }
+        }
+
+        if !genre_words.is_empty() {
+            let query = genre_words.join(" ").to_lowercase();
+            let genre = genres
+                .iter()
+                .find(|genre| genre.name.to_lowercase() == query)
+                .or_else(|| {
+                    // «фантаст» тоже подходит, если такой жанр один
+                    let mut matching = genres.iter().filter(|genre| genre.name.to_lowercase().starts_with(&query));
+                    matching.next().filter(|_| matching.next().is_none())
+                })
+                .ok_or_else(|| FilterError::UnknownGenre(genre_words.join(" ")))?;
+            filter.genre = Some(genre.id);
+        }
+        Ok(filter)
+    }
+
+    /// Данные кнопки «Ещё»: фильтр целиком, чтобы следующий фильм подбирался по тем же условиям.
+    /// Telegram ограничивает их 64 байтами, а здесь не больше 30.
+    pub fn to_callback(&self) -> String {
+        let (from, to) = self.years.map(|(from, to)| (from.to_string(), to.to_string())).unwrap_or_default();
+        format!(
+            "movie:next:{}:{}:{}:{}:{}",
+            self.genre.map(|id| id.to_string()).unwrap_or_default(),
+            from,
+            to,
+            self.min_rating.map(|rating| rating.to_string()).unwrap_or_default(),
+            self.original_language.as_deref().unwrap_or_default(),
+        )
+    }
+
+    /// Обратно из данных кнопки, без префикса `movie:next:`.
+    pub fn from_callback(data: &str) -> Option<Self> {
+        let parts: Vec<&str> = data.split(':').collect();
+        let [genre, from, to, rating, language] = parts[..] else {
+            return None;
+        };
+        let optional = |value: &str| (!value.is_empty()).then(|| value.to_string());
+        Some(MovieFilter {
+            genre: optional(genre).map(|id| id.parse()).transpose().ok()?,
+            years: match (from.parse(), to.parse()) {
+                (Ok(from), Ok(to)) => Some((from, to)),
+                _ => None,
+            },
+            min_rating: optional(rating).map(|rating| rating.parse()).transpose().ok()?,
+            original_language: optional(language),
+        })
+    }
+
+    fn query(&self) -> Vec<(&'static str, String)> {
+        let mut query = vec![
+            ("sort_by", "popularity.desc".to_string()),
+            ("include_adult", "false".to_string()),
+            ("vote_count.gte", MIN_VOTES.to_string()),
+        ];
+        if let Some(genre) = self.genre {
+            query.push(("with_genres", genre.to_string()));
+        }
+        if let Some((from, to)) = self.years {
+            query.push(("primary_release_date.gte", format!("{}-01-01", from)));
+            query.push(("primary_release_date.lte", format!("{}-12-31", to)));
+        }
+        if let Some(rating) = self.min_rating {
+            query.push(("vote_average.gte", rating.to_string()));
+        }
+        if let Some(language) = &self.original_language {
+            query.push(("with_original_language", language.clone()));
+        }
+        query
+    }
+}
+
+// «1995» или «1990-2000»; перепутанные местами годы меняются
+fn parse_years(word: &str) -> Option<(i32, i32)> {
+    let year = |s: &str| (s.len() == 4).then(|| s.parse::<i32>().ok()).flatten();
+    match word.split_once('-') {
+        Some((from, to)) => {
+            let (from, to) = (year(from)?, year(to)?);
+            Some((from.min(to), from.max(to)))
+        }
+        None => year(word).map(|year| (year, year)),
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Movie {
+    pub id: u64,
+    pub title: String,
+    #[serde(default)]
+    pub overview: String,
+    pub poster_path: Option<String>,
+}
+
+impl Movie {
+    pub fn poster_url(&self) -> Option<Url> {
+        Url::parse(&format!("https://image.tmdb.org/t/p/w500{}", self.poster_path.as_deref()?)).ok()
+    }
+
+    pub fn tmdb_url(&self) -> String {
+        format!("https://www.themoviedb.org/movie/{}", self.id)
+    }
+}
+
+#[derive(Deserialize)]
+struct DiscoverPage {
+    total_pages: u32,
+    results: Vec<Movie>,
+}
+
+#[derive(Deserialize)]
+struct GenreList {
+    genres: Vec<Genre>,
+}
+
+fn api_key() -> Result<String, FetchError> {
+    std::env::var("TMDB_API_KEY").map_err(|_| FetchError::NotConfigured("TMDB_API_KEY"))
+}
+
+// Язык названий и описаний в TMDB
+fn tmdb_language(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Ru => "ru-RU",
+        Lang::Uk => "uk-UA",
+        Lang::En => "en-US",
+    }
+}
+
+async fn request<T: serde::de::DeserializeOwned>(http: &HttpClient, path: &str, lang: Lang, params: &[(&str, String)]) -> Result<T, FetchError> {
+    let api_key = api_key()?;
+    let mut query = vec![("api_key", api_key.as_str()), ("language", tmdb_language(lang))];
+    query.extend(params.iter().map(|(name, value)| (*name, value.as_str())));
+    let body = http.get(Source::Tmdb, &format!("{}{}", API_URL, path), &query).await?;
+    serde_json::from_str(&body).map_err(|e| FetchError::Invalid(e.to_string()))
+}
+
+/// Фильм по id, например для кнопки «В список». Название — на языке чата.
+pub async fn details(http: &HttpClient, lang: Lang, id: u64) -> Result<Movie, FetchError> {
+    request(http, &format!("/movie/{}", id), lang, &[]).await
+}
+
+/// Жанры фильмов с названиями на языке чата.
+pub async fn genres(http: &HttpClient, lang: Lang) -> Result<Vec<Genre>, FetchError> {
+    Ok(request::<GenreList>(http, "/genre/movie/list", lang, &[]).await?.genres)
+}
+
+async fn discover(http: &HttpClient, lang: Lang, filter: &MovieFilter, page: u32) -> Result<DiscoverPage, FetchError> {
+    let mut params = filter.query();
+    params.push(("page", page.to_string()));
+    request(http, "/discover/movie", lang, &params).await
+}
+
+/// Случайный фильм с постером по фильтру, которого нет среди `seen` (там id фильмов).
+/// Если за несколько страниц новых не нашлось, повторяет уже предложенный; `None` — под фильтр ничего не подходит.
+pub async fn pick(http: &HttpClient, lang: Lang, filter: &MovieFilter, seen: &HashSet<String>) -> Result<Option<Movie>, FetchError> {
+    // Первая страница нужна, чтобы узнать число страниц; она же кэшируется и достаётся бесплатно
+    let first = discover(http, lang, filter, 1).await?;
+    let pages = first.total_pages.clamp(1, MAX_PAGE);
+
+    let mut repeat = None;
+    for _ in 0..PAGE_ATTEMPTS.min(pages as usize) {
+        let page = rand::thread_rng().gen_range(1..=pages);
+        let movies = if page == 1 { first.results.clone() } else { discover(http, lang, filter, page).await?.results };
+        let (fresh, old): (Vec<Movie>, Vec<Movie>) = movies
+            .into_iter()
+            .filter(|movie| movie.poster_path.is_some())
+            .partition(|movie| !seen.contains(&movie.id.to_string()));
+        if let Some(movie) = fresh.choose(&mut rand::thread_rng()) {
+            return Ok(Some(movie.clone()));
+        }
+        if repeat.is_none() {
+            repeat = old.choose(&mut rand::thread_rng()).cloned();
+        }
+    }
+    Ok(repeat)
+}
+
+/// Варианты опроса с наибольшим числом голосов; пусто, если никто не голосовал.
+pub fn poll_leaders(votes: &[u32]) -> Vec<usize> {
+    let best = votes.iter().copied().max().unwrap_or(0);
+    if best == 0 {
+        return Vec::new();
+    }
+    (0..votes.len()).filter(|&i| votes[i] == best).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genres() -> Vec<Genre> {
+        serde_json::from_str::<GenreList>(
+            r#"{"genres": [{"id": 35, "name": "комедия"}, {"id": 878, "name": "фантастика"},
+                           {"id": 18, "name": "драма"}, {"id": 10752, "name": "военный"}, {"id": 10751, "name": "семейный"}]}"#,
+        ).unwrap().genres
+    }
+
+    #[test]
+    fn parses_arguments_in_any_order() {
+        let filter = MovieFilter::parse("1990-2000 Комедия 7,5 fr", &genres()).unwrap();
+        assert_eq!(filter, MovieFilter {
+            genre: Some(35),
+            years: Some((1990, 2000)),
+            min_rating: Some(7.5),
+            original_language: Some("fr".to_string()),
+        });
+
+        assert_eq!(MovieFilter::parse("", &[]).unwrap(), MovieFilter::default());
+        assert_eq!(MovieFilter::parse("2010-2005", &[]).unwrap().years, Some((2005, 2010)));
+        assert_eq!(MovieFilter::parse("1984", &[]).unwrap().years, Some((1984, 1984)));
+        assert_eq!(MovieFilter::parse("FR комедия", &genres()).unwrap().original_language.as_deref(), Some("fr"));
+        assert_eq!(MovieFilter::parse("En", &[]).unwrap().original_language.as_deref(), Some("en"));
+    }
+
+    #[test]
+    fn genres_match_by_unique_prefix() {
+        assert_eq!(MovieFilter::parse("фантаст", &genres()).unwrap().genre, Some(878));
+        assert_eq!(MovieFilter::parse("д", &genres()).unwrap().genre, Some(18));
+
+        // С документальным «д» уже неоднозначно
+        let genres = [genres(), vec![Genre { id: 99, name: "документальный".to_string() }]].concat();
+        assert_eq!(MovieFilter::parse("д", &genres), Err(FilterError::UnknownGenre("д".to_string())));
+        assert_eq!(MovieFilter::parse("вестерн", &genres), Err(FilterError::UnknownGenre("вестерн".to_string())));
+        assert_eq!(MovieFilter::parse("11", &genres), Err(FilterError::BadNumber("11".to_string())));
+    }
+
+    #[test]
+    fn callback_data_round_trips() {
+        let filter = MovieFilter::parse("военный 1939-1945 8 ru", &genres()).unwrap();
+        let data = filter.to_callback();
+        assert!(data.len() <= 64, "{}", data);
+        assert_eq!(MovieFilter::from_callback(data.strip_prefix("movie:next:").unwrap()), Some(filter));
+
+        let empty = MovieFilter::default().to_callback();
+        assert_eq!(MovieFilter::from_callback(empty.strip_prefix("movie:next:").unwrap()), Some(MovieFilter::default()));
+        assert_eq!(MovieFilter::from_callback("мусор"), None);
+    }
+
+    #[test]
+    fn poll_leaders_include_ties() {
+        assert_eq!(poll_leaders(&[1, 4, 2]), [1]);
+        assert_eq!(poll_leaders(&[3, 0, 3]), [0, 2]);
+        assert!(poll_leaders(&[0, 0]).is_empty());
+    }
+
+    #[test]
+    fn discover_query_includes_only_set_filters() {
+        let query = MovieFilter { genre: Some(18), min_rating: Some(7.0), ..Default::default() }.query();
+        let names: Vec<&str> = query.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["sort_by", "include_adult", "vote_count.gte", "with_genres", "vote_average.gte"]);
+
+        let page: DiscoverPage = serde_json::from_str(
+            r#"{"page": 1, "total_pages": 2, "total_results": 2, "results": [
+                {"id": 1, "title": "С постером", "overview": "…", "poster_path": "/a.jpg"},
+                {"id": 2, "title": "Без постера", "poster_path": null}]}"#,
+        ).unwrap();
+        assert_eq!(page.results[0].poster_url().unwrap().as_str(), "https://image.tmdb.org/t/p/w500/a.jpg");
+        assert!(page.results[1].poster_url().is_none());
+    }
+}
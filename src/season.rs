@@ -0,0 +1,77 @@
+//! Сезоны: рейтинг периодически обнуляется, а итоги уходят в архив.
+//!
+//! Границы сезонов считаются в игровых днях чата (см. `cooldown`), так что сезон
+//! начинается в тот же час сброса, что и дневные лимиты.
+
+use chrono::{Datelike, Duration, Months, NaiveDate};
+
+/// Длина сезона. Задаётся через SEASON_LENGTH: `month`, `week` или число дней.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SeasonLength {
+    Month,
+    Days(u32),
+}
+
+/// Сезон — полуинтервал игровых дней `[start, end)`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Season {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+impl SeasonLength {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "month" => Some(SeasonLength::Month),
+            "week" => Some(SeasonLength::Days(7)),
+            days => days.parse().ok().filter(|days| *days > 0).map(SeasonLength::Days),
+        }
+    }
+
+    /// Сезон, в который попадает игровой день.
+    pub fn season_of(self, day: NaiveDate) -> Season {
+        match self {
+            SeasonLength::Month => {
+                let start = day.with_day(1).unwrap();
+                Season { start, end: start + Months::new(1) }
+            }
+            SeasonLength::Days(days) => {
+                // Отсчёт от понедельника, чтобы недельные сезоны начинались с начала недели
+                let anchor = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+                let index = (day - anchor).num_days().div_euclid(days as i64);
+                let start = anchor + Duration::days(index * days as i64);
+                Season { start, end: start + Duration::days(days as i64) }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn monthly_seasons() {
+        let season = SeasonLength::Month.season_of(date(2024, 12, 31));
+        assert_eq!(season, Season { start: date(2024, 12, 1), end: date(2025, 1, 1) });
+    }
+
+    #[test]
+    fn weekly_seasons_start_on_monday() {
+        let season = SeasonLength::from_name("week").unwrap().season_of(date(2024, 11, 24));
+        assert_eq!(season, Season { start: date(2024, 11, 18), end: date(2024, 11, 25) });
+        // Дни до точки отсчёта тоже делятся на сезоны правильно
+        assert_eq!(SeasonLength::Days(7).season_of(date(2023, 12, 31)).start, date(2023, 12, 25));
+    }
+
+    #[test]
+    fn parses_length() {
+        assert_eq!(SeasonLength::from_name("Month"), Some(SeasonLength::Month));
+        assert_eq!(SeasonLength::from_name("14"), Some(SeasonLength::Days(14)));
+        assert_eq!(SeasonLength::from_name("0"), None);
+    }
+}
@@ -0,0 +1,97 @@
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use chrono::Local;
+
+/// Настройки резервных копий файла с данными.
+#[derive(Clone, Debug)]
+pub struct BackupPolicy {
+    pub dir: PathBuf,
+    /// Сколько последних копий хранить
+    pub keep: usize,
+    /// Не чаще одной копии за этот интервал
+    pub interval: Duration,
+}
+
+impl Default for BackupPolicy {
+    fn default() -> Self {
+        Self {
+            dir: PathBuf::from("backups"),
+            keep: 5,
+            interval: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+impl BackupPolicy {
+    /// Копирует файл в каталог резервных копий, если с прошлой копии прошло достаточно времени,
+    /// и удаляет лишние старые копии.
+    pub fn backup(&self, file: &Path) -> std::io::Result<()> {
+        if self.keep == 0 || !file.exists() {
+            return Ok(());
+        }
+
+        let backups = self.list(file)?;
+        let recent = backups.last()
+            .and_then(|last| fs::metadata(last).and_then(|m| m.modified()).ok())
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|age| age < self.interval);
+        if recent {
+            return Ok(());
+        }
+
+        fs::create_dir_all(&self.dir)?;
+        let name = format!("{}-{}.{}", stem(file), Local::now().format("%Y%m%d-%H%M%S"), extension(file));
+        fs::copy(file, self.dir.join(name))?;
+
+        let backups = self.list(file)?;
+        for old in backups.iter().take(backups.len().saturating_sub(self.keep)) {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+
+    /// Резервные копии файла, от старых к новым.
+    pub fn list(&self, file: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let prefix = format!("{}-", stem(file));
+        let suffix = format!(".{}", extension(file));
+        let mut backups: Vec<PathBuf> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| {
+                    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+                    name.starts_with(&prefix) && name.ends_with(&suffix)
+                })
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        // Метка времени в имени сортируется лексикографически
+        backups.sort();
+        Ok(backups)
+    }
+}
+
+/// Записывает файл через временный файл и переименование,
+/// чтобы при падении процесса на диске остался либо старый, либо новый файл целиком.
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut file = fs::File::create(&tmp_path)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    drop(file);
+
+    fs::rename(&tmp_path, path)
+}
+
+fn stem(file: &Path) -> &str {
+    file.file_stem().and_then(|s| s.to_str()).unwrap_or("data")
+}
+
+fn extension(file: &Path) -> &str {
+    file.extension().and_then(|s| s.to_str()).unwrap_or("bak")
+}
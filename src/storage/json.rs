@@ -0,0 +1,537 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Utc};
+use serde::{Serialize, Deserialize};
+use crate::models::{ChatSettings, MoviePoll, SeasonResult, SeenItem, SeenKind, SizeEvent, UnlockedAchievement, UserData, WatchlistEntry};
+use super::backup::{write_atomic, BackupPolicy};
+use super::migrations::{self, CURRENT_VERSION};
+use super::{Snapshot, Storage, StorageError};
+
+/// Содержимое users.json: пользователи сгруппированы по чатам.
+#[derive(Clone, Serialize, Deserialize)]
+struct UsersFile {
+    version: u32,
+    chats: HashMap<i64, HashMap<i64, UserData>>,
+    // Записи из старого плоского формата, ещё не привязанные к чату.
+    // Пользователь забирает свою запись в первый чат, где вызовет команду.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    legacy: HashMap<i64, UserData>,
+    /// История изменений размера по чатам
+    history: HashMap<i64, Vec<SizeEvent>>,
+    settings: HashMap<i64, ChatSettings>,
+    /// Открытые достижения по чатам
+    achievements: HashMap<i64, Vec<UnlockedAchievement>>,
+    /// Архив завершённых сезонов по чатам
+    seasons: HashMap<i64, Vec<SeasonResult>>,
+    /// Недавно показанные анекдоты и прочее по чатам
+    seen: HashMap<i64, Vec<SeenItem>>,
+    /// Списки фильмов «посмотреть» по чатам
+    watchlist: HashMap<i64, Vec<WatchlistEntry>>,
+    /// Голосования за фильм, которые ещё не закрылись
+    movie_polls: Vec<MoviePoll>,
+}
+
+impl Default for UsersFile {
+    fn default() -> Self {
+        Self {
+            version: CURRENT_VERSION,
+            chats: HashMap::new(),
+            legacy: HashMap::new(),
+            history: HashMap::new(),
+            settings: HashMap::new(),
+            achievements: HashMap::new(),
+            seasons: HashMap::new(),
+            seen: HashMap::new(),
+            watchlist: HashMap::new(),
+            movie_polls: Vec::new(),
+        }
+    }
+}
+
+impl UsersFile {
+    /// Разбирает документ любой поддерживаемой версии, поднимая его до текущей.
+    fn parse(data: &str) -> Result<Self, StorageError> {
+        let value: serde_json::Value = serde_json::from_str(data)?;
+        let users: Self = serde_json::from_value(migrations::migrate(value)?)?;
+        if !users.legacy.is_empty() {
+            log::info!("{} пользователей из старого формата ждут привязки к чату", users.legacy.len());
+        }
+        Ok(users)
+    }
+
+    fn read(path: &Path) -> Result<Self, StorageError> {
+        let data = fs::read_to_string(path)?;
+        Self::parse(&data)
+    }
+}
+
+/// Хранилище в одном JSON-файле. Файл целиком переписывается при каждом сохранении.
+pub struct JsonStorage {
+    file_path: PathBuf,
+    backups: BackupPolicy,
+    users: UsersFile,
+}
+
+impl JsonStorage {
+    /// Открывает файл с данными. Если файл повреждён, восстанавливает самую свежую
+    /// читаемую резервную копию, а если таких нет — возвращает ошибку.
+    pub fn open(file_path: &str, backups: BackupPolicy) -> Result<Self, StorageError> {
+        let file_path = PathBuf::from(file_path);
+        let users = if file_path.exists() {
+            match UsersFile::read(&file_path) {
+                Ok(users) => users,
+                // Файл более новой версии не трогаем, чтобы не затереть его старой копией
+                Err(e @ StorageError::UnsupportedVersion { .. }) => return Err(e),
+                Err(e) => {
+                    log::error!("Не удалось прочитать {}: {}", file_path.display(), e);
+                    restore_backup(&file_path, &backups)?.ok_or(e)?
+                }
+            }
+        } else {
+            UsersFile::default()
+        };
+
+        Ok(Self {
+            file_path,
+            backups,
+            users,
+        })
+    }
+
+    /// Отдаёт все данные для переноса в другое хранилище.
+    pub fn into_snapshot(self) -> Snapshot {
+        Snapshot {
+            users: self.users.chats.into_values().flat_map(|chat| chat.into_values()).collect(),
+            legacy: self.users.legacy.into_values().collect(),
+            history: self.users.history.into_values().flatten().collect(),
+            settings: self.users.settings.into_values().collect(),
+            achievements: self.users.achievements.into_values().flatten().collect(),
+            seasons: self.users.seasons.into_values().flatten().collect(),
+            seen: self.users.seen.into_values().flatten().collect(),
+            watchlist: self.users.watchlist.into_values().flatten().collect(),
+            movie_polls: self.users.movie_polls,
+        }
+    }
+
+    /// Меняет копию данных и записывает её в файл. Данные в памяти заменяются только после
+    /// успешной записи, чтобы при ошибке не работать дальше с несохранённым состоянием.
+    fn update<R>(&mut self, change: impl FnOnce(&mut UsersFile) -> R) -> Result<R, StorageError> {
+        let mut users = self.users.clone();
+        let result = change(&mut users);
+        self.save(&users)?;
+        self.users = users;
+        Ok(result)
+    }
+
+    fn save(&self, users: &UsersFile) -> Result<(), StorageError> {
+        let data = serde_json::to_string_pretty(users)?;
+        if let Err(e) = self.backups.backup(&self.file_path) {
+            log::warn!("Не удалось создать резервную копию {}: {}", self.file_path.display(), e);
+        }
+        write_atomic(&self.file_path, data.as_bytes())?;
+        Ok(())
+    }
+}
+
+// Ищет самую свежую читаемую копию и возвращает её содержимое на место повреждённого файла
+fn restore_backup(file_path: &Path, backups: &BackupPolicy) -> Result<Option<UsersFile>, StorageError> {
+    for backup in backups.list(file_path)?.iter().rev() {
+        match UsersFile::read(backup) {
+            Ok(users) => {
+                log::warn!("Восстанавливаем {} из резервной копии {}", file_path.display(), backup.display());
+                let mut broken = file_path.as_os_str().to_owned();
+                broken.push(".broken");
+                fs::rename(file_path, broken)?;
+                fs::copy(backup, file_path)?;
+                return Ok(Some(users));
+            }
+            Err(e) => log::warn!("Резервная копия {} тоже повреждена: {}", backup.display(), e),
+        }
+    }
+    Ok(None)
+}
+
+impl Storage for JsonStorage {
+    fn get_user(&mut self, chat_id: i64, user_id: i64) -> Result<Option<UserData>, StorageError> {
+        if let Some(user) = self.users.chats.get(&chat_id).and_then(|chat| chat.get(&user_id)) {
+            return Ok(Some(user.clone()));
+        }
+
+        if !self.users.legacy.contains_key(&user_id) {
+            return Ok(None);
+        }
+        self.update(|users| {
+            let user = UserData { chat_id, ..users.legacy.remove(&user_id)? };
+            users.chats.entry(chat_id).or_default().insert(user_id, user.clone());
+            Some(user)
+        })
+    }
+
+    fn save_user(&mut self, user: &UserData) -> Result<(), StorageError> {
+        self.update(|users| {
+            users.chats
+                .entry(user.chat_id)
+                .or_default()
+                .insert(user.user_id, user.clone());
+        })
+    }
+
+    fn chat_users(&mut self, chat_id: i64) -> Result<Vec<UserData>, StorageError> {
+        Ok(self.users.chats
+            .get(&chat_id)
+            .map(|chat| chat.values().cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn save_batch(&mut self, changed: &[UserData], events: &[SizeEvent]) -> Result<(), StorageError> {
+        self.update(|users| {
+            for user in changed {
+                users.chats.entry(user.chat_id).or_default().insert(user.user_id, user.clone());
+            }
+            for event in events {
+                users.history.entry(event.chat_id).or_default().push(event.clone());
+            }
+        })
+    }
+
+    fn user_events(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<SizeEvent>, StorageError> {
+        Ok(self.users.history
+            .get(&chat_id)
+            .map(|events| events.iter().filter(|e| e.user_id == user_id).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn chat_events(&mut self, chat_id: i64) -> Result<Vec<SizeEvent>, StorageError> {
+        Ok(self.users.history.get(&chat_id).cloned().unwrap_or_default())
+    }
+
+    fn chat_settings(&mut self, chat_id: i64) -> Result<ChatSettings, StorageError> {
+        Ok(self.users.settings
+            .get(&chat_id)
+            .cloned()
+            .unwrap_or_else(|| ChatSettings::new(chat_id)))
+    }
+
+    fn save_chat_settings(&mut self, settings: &ChatSettings) -> Result<(), StorageError> {
+        self.update(|users| {
+            users.settings.insert(settings.chat_id, settings.clone());
+        })
+    }
+
+    fn user_achievements(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<UnlockedAchievement>, StorageError> {
+        Ok(self.users.achievements
+            .get(&chat_id)
+            .map(|unlocked| unlocked.iter().filter(|a| a.user_id == user_id).cloned().collect())
+            .unwrap_or_default())
+    }
+
+    fn save_achievement(&mut self, unlocked: &UnlockedAchievement) -> Result<(), StorageError> {
+        self.update(|users| users.achievements.entry(unlocked.chat_id).or_default().push(unlocked.clone()))
+    }
+
+    fn chat_seasons(&mut self, chat_id: i64) -> Result<Vec<SeasonResult>, StorageError> {
+        Ok(self.users.seasons.get(&chat_id).cloned().unwrap_or_default())
+    }
+
+    fn save_season(&mut self, result: &SeasonResult) -> Result<(), StorageError> {
+        self.update(|users| {
+            let seasons = users.seasons.entry(result.chat_id).or_default();
+            seasons.retain(|s| s.start != result.start);
+            seasons.push(result.clone());
+            seasons.sort_by_key(|s| s.start);
+        })
+    }
+
+    fn seen_since(&mut self, chat_id: i64, kind: SeenKind, since: DateTime<Utc>) -> Result<Vec<String>, StorageError> {
+        Ok(self.users.seen
+            .get(&chat_id)
+            .map(|seen| seen.iter().filter(|s| s.kind == kind && s.at >= since).map(|s| s.key.clone()).collect())
+            .unwrap_or_default())
+    }
+
+    fn mark_seen(&mut self, item: &SeenItem, forget_before: DateTime<Utc>) -> Result<(), StorageError> {
+        self.update(|users| {
+            let seen = users.seen.entry(item.chat_id).or_default();
+            seen.retain(|s| s.kind != item.kind || (s.at >= forget_before && s.key != item.key));
+            seen.push(item.clone());
+        })
+    }
+
+    fn watchlist(&mut self, chat_id: i64) -> Result<Vec<WatchlistEntry>, StorageError> {
+        Ok(self.users.watchlist.get(&chat_id).cloned().unwrap_or_default())
+    }
+
+    fn add_to_watchlist(&mut self, entry: &WatchlistEntry) -> Result<bool, StorageError> {
+        let known = self.users.watchlist.get(&entry.chat_id).is_some_and(|watchlist| watchlist.iter().any(|e| e.movie_id == entry.movie_id));
+        if known {
+            return Ok(false);
+        }
+        self.update(|users| users.watchlist.entry(entry.chat_id).or_default().push(entry.clone()))?;
+        Ok(true)
+    }
+
+    fn remove_from_watchlist(&mut self, chat_id: i64, movie_id: u64) -> Result<(), StorageError> {
+        self.update(|users| {
+            if let Some(watchlist) = users.watchlist.get_mut(&chat_id) {
+                watchlist.retain(|e| e.movie_id != movie_id);
+            }
+        })
+    }
+
+    fn save_movie_poll(&mut self, poll: &MoviePoll) -> Result<(), StorageError> {
+        self.update(|users| users.movie_polls.push(poll.clone()))
+    }
+
+    fn take_movie_poll(&mut self, poll_id: &str) -> Result<Option<MoviePoll>, StorageError> {
+        let Some(pos) = self.users.movie_polls.iter().position(|p| p.poll_id == poll_id) else {
+            return Ok(None);
+        };
+        self.update(|users| Some(users.movie_polls.remove(pos)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Achievement, ChangeSource};
+
+    const CHAT: i64 = -1001234567890;
+
+    fn fixture(name: &str) -> String {
+        let path = format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name);
+        fs::read_to_string(path).unwrap()
+    }
+
+    // Хранилище без резервных копий со своим путём во временном каталоге, чтобы параллельные тесты
+    // не писали в один файл. Сам файл появится только при первой записи, тест его удаляет
+    fn storage(users: UsersFile) -> JsonStorage {
+        let file_path = tempfile::NamedTempFile::new().unwrap().path().to_path_buf();
+        JsonStorage {
+            file_path,
+            backups: BackupPolicy { keep: 0, ..BackupPolicy::default() },
+            users,
+        }
+    }
+
+    #[test]
+    fn loads_v1_flat_map_into_legacy() {
+        let users = UsersFile::parse(&fixture("users_v1.json")).unwrap();
+
+        assert_eq!(users.version, CURRENT_VERSION);
+        assert!(users.chats.is_empty());
+        assert_eq!(users.legacy.len(), 2);
+        assert_eq!(users.legacy[&1001].pisun, 17);
+        assert_eq!(users.legacy[&1002].pisun, 0);
+    }
+
+    #[test]
+    fn loads_v2_chats_and_legacy() {
+        let users = UsersFile::parse(&fixture("users_v2.json")).unwrap();
+
+        assert_eq!(users.version, CURRENT_VERSION);
+        assert_eq!(users.chats[&CHAT][&1001].chat_id, CHAT);
+        assert_eq!(users.chats[&CHAT][&1001].pisun, 17);
+        assert_eq!(users.legacy[&1002].pisun, 4);
+    }
+
+    #[test]
+    fn loads_v3_versioned_file() {
+        let users = UsersFile::parse(&fixture("users_v3.json")).unwrap();
+
+        assert_eq!(users.version, CURRENT_VERSION);
+        assert_eq!(users.chats[&CHAT].len(), 2);
+        assert_eq!(users.chats[&CHAT][&1003].pisun, 25);
+        assert!(users.legacy.is_empty());
+        assert!(users.history.is_empty());
+    }
+
+    #[test]
+    fn loads_v4_history() {
+        let mut storage = storage(UsersFile::parse(&fixture("users_v4.json")).unwrap());
+
+        let events = storage.user_events(CHAT, 1001).unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!((events[1].source, events[1].delta, events[1].size), (ChangeSource::Training, -1, 19));
+        assert!(storage.user_events(CHAT, 1002).unwrap().is_empty());
+    }
+
+    #[test]
+    fn legacy_user_is_claimed_by_first_chat() {
+        let mut storage = storage(UsersFile::parse(&fixture("users_v1.json")).unwrap());
+
+        let user = storage.get_user(42, 1001).unwrap().unwrap();
+        assert_eq!((user.chat_id, user.pisun), (42, 17));
+        assert!(storage.get_user(43, 1001).unwrap().is_none());
+        let _ = fs::remove_file(&storage.file_path);
+    }
+
+    #[test]
+    fn loads_v5_profiles() {
+        let users = UsersFile::parse(&fixture("users_v5.json")).unwrap();
+        let chat = &users.chats[&CHAT];
+
+        assert_eq!(chat[&1001].display_name(), "Вася Пупкин");
+        assert_eq!(chat[&1002].display_name(), "@petya");
+        assert_eq!(chat[&1003].display_name(), "Аноним #1003");
+    }
+
+    #[test]
+    fn older_versions_get_empty_profiles() {
+        let users = UsersFile::parse(&fixture("users_v4.json")).unwrap();
+        let user = &users.chats[&CHAT][&1001];
+
+        assert!(user.username.is_none() && user.first_name.is_none() && user.last_name.is_none());
+    }
+
+    #[test]
+    fn loads_v6_chat_settings() {
+        let mut storage = storage(UsersFile::parse(&fixture("users_v6.json")).unwrap());
+
+        let settings = storage.chat_settings(CHAT).unwrap();
+        assert_eq!(settings.timezone.as_deref(), Some("Europe/Moscow"));
+        assert_eq!(settings.reset_hour, Some(6));
+        assert!(storage.chat_settings(42).unwrap().timezone.is_none());
+    }
+
+    #[test]
+    fn loads_v7_last_duel() {
+        let users = UsersFile::parse(&fixture("users_v7.json")).unwrap();
+        let chat = &users.chats[&CHAT];
+
+        assert!(chat[&1001].last_duel.is_some());
+        assert!(chat[&1002].last_duel.is_none());
+        let older = UsersFile::parse(&fixture("users_v6.json")).unwrap();
+        assert!(older.chats[&CHAT][&1001].last_duel.is_none());
+    }
+
+    #[test]
+    fn loads_v8_achievements() {
+        let mut storage = storage(UsersFile::parse(&fixture("users_v8.json")).unwrap());
+
+        let unlocked = storage.user_achievements(CHAT, 1001).unwrap();
+        let achievements: Vec<_> = unlocked.iter().map(|a| a.achievement).collect();
+        assert_eq!(achievements, [Achievement::FirstCentimetre, Achievement::Jackpot]);
+        assert!(storage.user_achievements(CHAT, 1002).unwrap().is_empty());
+    }
+
+    #[test]
+    fn loads_v9_streaks() {
+        let users = UsersFile::parse(&fixture("users_v9.json")).unwrap();
+        let user = &users.chats[&CHAT][&1001];
+        assert_eq!((user.roll_streak.current, user.roll_streak.best), (4, 9));
+        assert_eq!(user.train_streak.best, 2);
+
+        let older = UsersFile::parse(&fixture("users_v8.json")).unwrap();
+        assert_eq!(older.chats[&CHAT][&1001].roll_streak.best, 0);
+    }
+
+    #[test]
+    fn loads_v10_seasons() {
+        let users = UsersFile::parse(&fixture("users_v10.json")).unwrap();
+
+        let seasons = &users.seasons[&CHAT];
+        assert_eq!(seasons.len(), 1);
+        assert_eq!(seasons[0].winner().map(|w| w.user_id), Some(1001));
+        assert_eq!(users.settings[&CHAT].season_start, seasons[0].end.into());
+
+        let older = UsersFile::parse(&fixture("users_v6.json")).unwrap();
+        assert!(older.seasons.is_empty() && older.settings[&CHAT].season_start.is_none());
+    }
+
+    #[test]
+    fn loads_v11_language() {
+        let users = UsersFile::parse(&fixture("users_v11.json")).unwrap();
+        assert_eq!(users.settings[&CHAT].language.as_deref(), Some("uk"));
+
+        let older = UsersFile::parse(&fixture("users_v10.json")).unwrap();
+        assert_eq!(older.version, CURRENT_VERSION);
+        assert!(older.settings[&CHAT].language.is_none());
+    }
+
+    #[test]
+    fn loads_v12_location() {
+        let users = UsersFile::parse(&fixture("users_v12.json")).unwrap();
+        let location = users.settings[&CHAT].location.as_ref().unwrap();
+        assert_eq!((location.name.as_str(), location.timezone.as_str()), ("Берлин, Германия", "Europe/Berlin"));
+
+        let older = UsersFile::parse(&fixture("users_v11.json")).unwrap();
+        assert!(older.settings[&CHAT].location.is_none());
+    }
+
+    #[test]
+    fn loads_v13_seen() {
+        let mut storage = storage(UsersFile::parse(&fixture("users_v13.json")).unwrap());
+        let since = |day| "2024-11-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + chrono::Duration::days(day);
+
+        assert_eq!(storage.seen_since(CHAT, SeenKind::Joke, since(0)).unwrap(), ["5f1d4c3ab2e09d77", "0b9e6a4f3c2d1e80"]);
+        assert_eq!(storage.seen_since(CHAT, SeenKind::Joke, since(10)).unwrap(), ["0b9e6a4f3c2d1e80"]);
+
+        // Повторный показ обновляет время, а старые показы забываются
+        let item = SeenItem { chat_id: CHAT, kind: SeenKind::Joke, key: "0b9e6a4f3c2d1e80".to_string(), at: since(20) };
+        storage.mark_seen(&item, since(10)).unwrap();
+        assert_eq!(storage.users.seen[&CHAT].len(), 1);
+        let _ = fs::remove_file(&storage.file_path);
+
+        let older = UsersFile::parse(&fixture("users_v12.json")).unwrap();
+        assert!(older.seen.is_empty());
+    }
+
+    #[test]
+    fn loads_v14_watchlist() {
+        let mut storage = storage(UsersFile::parse(&fixture("users_v14.json")).unwrap());
+        let titles = |storage: &mut JsonStorage| storage.watchlist(CHAT).unwrap().into_iter().map(|e| e.title).collect::<Vec<_>>();
+        assert_eq!(titles(&mut storage), ["Сталкер", "Брат"]);
+
+        let mut entry = storage.watchlist(CHAT).unwrap()[0].clone();
+        assert!(!storage.add_to_watchlist(&entry).unwrap());
+        entry.movie_id = 593;
+        entry.title = "Солярис".to_string();
+        assert!(storage.add_to_watchlist(&entry).unwrap());
+        storage.remove_from_watchlist(CHAT, 593).unwrap();
+        assert_eq!(titles(&mut storage), ["Сталкер", "Брат"]);
+
+        // Закрытое голосование забирается один раз
+        assert_eq!(storage.take_movie_poll("5420412345678901").unwrap().unwrap().movie_ids, [1398, 20992]);
+        assert!(storage.take_movie_poll("5420412345678901").unwrap().is_none());
+        let _ = fs::remove_file(&storage.file_path);
+
+        let older = UsersFile::parse(&fixture("users_v13.json")).unwrap();
+        assert!(older.watchlist.is_empty() && older.movie_polls.is_empty());
+    }
+
+    #[test]
+    fn failed_write_keeps_memory_unchanged() {
+        let mut storage = storage(UsersFile::parse(&fixture("users_v4.json")).unwrap());
+        // Каталога нет, запись не удастся
+        storage.file_path = storage.file_path.join("users.json");
+        let mut user = storage.get_user(CHAT, 1001).unwrap().unwrap();
+        user.pisun += 10;
+        let event = SizeEvent { size: user.pisun, delta: 10, ..storage.user_events(CHAT, 1001).unwrap()[0].clone() };
+
+        assert!(storage.save_batch(&[user], &[event]).is_err());
+        assert_eq!(storage.get_user(CHAT, 1001).unwrap().unwrap().pisun, 19);
+        assert_eq!(storage.user_events(CHAT, 1001).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn restores_backup_of_misshaped_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("users.json");
+        let backups = BackupPolicy { dir: dir.path().join("backups"), ..BackupPolicy::default() };
+        fs::create_dir_all(&backups.dir).unwrap();
+        fs::copy(format!("{}/tests/fixtures/users_v3.json", env!("CARGO_MANIFEST_DIR")), backups.dir.join("users-20240101-000000.json")).unwrap();
+        // Файл обрезан до массива: разбирается как JSON, но миграция на нём падает
+        fs::write(&file_path, "[1, 2]").unwrap();
+
+        let mut storage = JsonStorage::open(file_path.to_str().unwrap(), backups).unwrap();
+        assert_eq!(storage.get_user(CHAT, 1003).unwrap().unwrap().pisun, 25);
+        assert!(dir.path().join("users.json.broken").exists());
+    }
+
+    #[test]
+    fn rejects_newer_version() {
+        let result = UsersFile::parse(r#"{ "version": 999, "chats": {} }"#);
+        assert!(matches!(result, Err(StorageError::UnsupportedVersion { found: 999, .. })));
+    }
+}
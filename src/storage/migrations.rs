@@ -0,0 +1,218 @@
+//! Миграции формата users.json.
+//!
+//! Каждая версия формата получает свой номер. Старый документ поднимается
+//! до текущей версии по одному шагу, так что для новой версии достаточно
+//! дописать одну функцию в `MIGRATIONS` и увеличить `CURRENT_VERSION`.
+//!
+//! История версий:
+//! 1. `{ "<user_id>": UserData }` — общий список без разделения по чатам.
+//! 2. `{ "chats": { "<chat_id>": { "<user_id>": UserData } }, "legacy": {...} }`.
+//! 3. То же, что 2, плюс поле `version` в корне документа.
+//! 4. Добавлена история изменений размера: `"history": { "<chat_id>": [SizeEvent] }`.
+//! 5. У пользователей появились `username`, `first_name` и `last_name`.
+//! 6. Добавлены настройки чатов: `"settings": { "<chat_id>": ChatSettings }`.
+//! 7. У пользователей появилось `last_duel` — время последней дуэли.
+//! 8. Добавлены достижения: `"achievements": { "<chat_id>": [UnlockedAchievement] }`.
+//! 9. У пользователей появились серии `roll_streak` и `train_streak`: `{ "current", "best" }`.
+//! 10. Добавлен архив сезонов `"seasons": { "<chat_id>": [SeasonResult] }`,
+//!     в настройках чатов — `season_start`.
+//! 11. В настройках чатов появился язык ответов `language`.
+//! 12. В настройках чатов появился город для погоды `location`.
+//! 13. Добавлены недавние показы `"seen": { "<chat_id>": [SeenItem] }`, чтобы не повторять анекдоты.
+//! 14. Добавлены списки фильмов `"watchlist": { "<chat_id>": [WatchlistEntry] }`
+//!     и открытые голосования `"movie_polls": [MoviePoll]`.
+
+use serde_json::{json, Map, Value};
+use super::StorageError;
+
+pub const CURRENT_VERSION: u32 = 14;
+
+type Migration = fn(Value) -> Result<Value, StorageError>;
+
+/// Шаги миграции: элемент с индексом `i` переводит документ из версии `i + 1` в `i + 2`.
+const MIGRATIONS: &[Migration] = &[
+    v1_to_v2,
+    v2_to_v3,
+    v3_to_v4,
+    v4_to_v5,
+    v5_to_v6,
+    v6_to_v7,
+    v7_to_v8,
+    v8_to_v9,
+    v9_to_v10,
+    v10_to_v11,
+    v11_to_v12,
+    v12_to_v13,
+    v13_to_v14,
+];
+
+/// Определяет версию документа. До версии 3 номер не хранился и угадывается по структуре.
+pub fn detect_version(doc: &Value) -> Result<u32, StorageError> {
+    match doc.get("version") {
+        Some(version) => version
+            .as_u64()
+            .map(|v| v as u32)
+            .ok_or_else(|| StorageError::Migration(format!("некорректный номер версии: {}", version))),
+        None if doc.get("chats").is_some() => Ok(2),
+        None => Ok(1),
+    }
+}
+
+/// Поднимает документ до текущей версии.
+pub fn migrate(mut doc: Value) -> Result<Value, StorageError> {
+    let mut version = detect_version(&doc)?;
+    if version > CURRENT_VERSION {
+        return Err(StorageError::UnsupportedVersion { found: version, supported: CURRENT_VERSION });
+    }
+    if version == 0 {
+        return Err(StorageError::Migration("некорректный номер версии: 0".to_string()));
+    }
+
+    while version < CURRENT_VERSION {
+        doc = MIGRATIONS[(version - 1) as usize](doc)?;
+        version += 1;
+        log::info!("Данные пользователей обновлены до версии {}", version);
+    }
+    Ok(doc)
+}
+
+fn object(doc: Value, version: u32) -> Result<Map<String, Value>, StorageError> {
+    match doc {
+        Value::Object(map) => Ok(map),
+        other => Err(StorageError::Migration(format!(
+            "документ версии {} должен быть объектом, а не {}",
+            version, other
+        ))),
+    }
+}
+
+// Пользователи из общего списка уходят в legacy и привязываются к чату при первой команде
+fn v1_to_v2(doc: Value) -> Result<Value, StorageError> {
+    let legacy = object(doc, 1)?;
+    Ok(json!({ "chats": {}, "legacy": legacy }))
+}
+
+fn v2_to_v3(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 2)?;
+    doc.insert("version".to_string(), json!(3));
+    Ok(Value::Object(doc))
+}
+
+fn v3_to_v4(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 3)?;
+    doc.insert("version".to_string(), json!(4));
+    doc.entry("history").or_insert_with(|| json!({}));
+    Ok(Value::Object(doc))
+}
+
+fn v4_to_v5(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 4)?;
+    doc.insert("version".to_string(), json!(5));
+    for_each_user(&mut doc, |user| {
+        for field in ["username", "first_name", "last_name"] {
+            user.entry(field).or_insert(Value::Null);
+        }
+    });
+    Ok(Value::Object(doc))
+}
+
+fn v5_to_v6(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 5)?;
+    doc.insert("version".to_string(), json!(6));
+    doc.entry("settings").or_insert_with(|| json!({}));
+    Ok(Value::Object(doc))
+}
+
+fn v6_to_v7(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 6)?;
+    doc.insert("version".to_string(), json!(7));
+    for_each_user(&mut doc, |user| {
+        user.entry("last_duel").or_insert(Value::Null);
+    });
+    Ok(Value::Object(doc))
+}
+
+fn v7_to_v8(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 7)?;
+    doc.insert("version".to_string(), json!(8));
+    doc.entry("achievements").or_insert_with(|| json!({}));
+    Ok(Value::Object(doc))
+}
+
+// Серии начинаются с нуля: восстанавливать их по истории ненадёжно, она есть не у всех
+fn v8_to_v9(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 8)?;
+    doc.insert("version".to_string(), json!(9));
+    for_each_user(&mut doc, |user| {
+        for field in ["roll_streak", "train_streak"] {
+            user.entry(field).or_insert_with(|| json!({ "current": 0, "best": 0 }));
+        }
+    });
+    Ok(Value::Object(doc))
+}
+
+fn v9_to_v10(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 9)?;
+    doc.insert("version".to_string(), json!(10));
+    doc.entry("seasons").or_insert_with(|| json!({}));
+    if let Some(settings) = doc.get_mut("settings").and_then(Value::as_object_mut) {
+        for chat in settings.values_mut().filter_map(Value::as_object_mut) {
+            chat.entry("season_start").or_insert(Value::Null);
+        }
+    }
+    Ok(Value::Object(doc))
+}
+
+fn v10_to_v11(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 10)?;
+    doc.insert("version".to_string(), json!(11));
+    if let Some(settings) = doc.get_mut("settings").and_then(Value::as_object_mut) {
+        for chat in settings.values_mut().filter_map(Value::as_object_mut) {
+            chat.entry("language").or_insert(Value::Null);
+        }
+    }
+    Ok(Value::Object(doc))
+}
+
+fn v11_to_v12(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 11)?;
+    doc.insert("version".to_string(), json!(12));
+    if let Some(settings) = doc.get_mut("settings").and_then(Value::as_object_mut) {
+        for chat in settings.values_mut().filter_map(Value::as_object_mut) {
+            chat.entry("location").or_insert(Value::Null);
+        }
+    }
+    Ok(Value::Object(doc))
+}
+
+fn v12_to_v13(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 12)?;
+    doc.insert("version".to_string(), json!(13));
+    doc.entry("seen").or_insert_with(|| json!({}));
+    Ok(Value::Object(doc))
+}
+
+fn v13_to_v14(doc: Value) -> Result<Value, StorageError> {
+    let mut doc = object(doc, 13)?;
+    doc.insert("version".to_string(), json!(14));
+    doc.entry("watchlist").or_insert_with(|| json!({}));
+    doc.entry("movie_polls").or_insert_with(|| json!([]));
+    Ok(Value::Object(doc))
+}
+
+// Обходит пользователей во всех чатах и в legacy
+fn for_each_user(doc: &mut Map<String, Value>, mut f: impl FnMut(&mut Map<String, Value>)) {
+    if let Some(chats) = doc.get_mut("chats").and_then(Value::as_object_mut) {
+        let users = chats.values_mut()
+            .filter_map(Value::as_object_mut)
+            .flat_map(|chat| chat.values_mut());
+        for user in users.filter_map(Value::as_object_mut) {
+            f(user);
+        }
+    }
+    if let Some(legacy) = doc.get_mut("legacy").and_then(Value::as_object_mut) {
+        for user in legacy.values_mut().filter_map(Value::as_object_mut) {
+            f(user);
+        }
+    }
+}
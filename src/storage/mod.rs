@@ -0,0 +1,173 @@
+mod backup;
+mod json;
+mod migrations;
+mod sqlite;
+
+use std::fmt;
+use chrono::{DateTime, Utc};
+use crate::models::{ChatSettings, MoviePoll, SeasonResult, SeenItem, SeenKind, SizeEvent, UnlockedAchievement, UserData, WatchlistEntry};
+
+pub use backup::BackupPolicy;
+pub use json::JsonStorage;
+pub use sqlite::SqliteStorage;
+
+/// Хранилище данных пользователей. Все данные разбиты по чатам.
+pub trait Storage: Send {
+    /// Возвращает пользователя чата, если он уже есть в хранилище.
+    fn get_user(&mut self, chat_id: i64, user_id: i64) -> Result<Option<UserData>, StorageError>;
+
+    /// Сохраняет пользователя, создавая запись при необходимости.
+    fn save_user(&mut self, user: &UserData) -> Result<(), StorageError>;
+
+    /// Все пользователи чата в произвольном порядке.
+    fn chat_users(&mut self, chat_id: i64) -> Result<Vec<UserData>, StorageError>;
+
+    /// Сохраняет несколько пользователей и их события разом: либо всё, либо ничего.
+    fn save_batch(&mut self, users: &[UserData], events: &[SizeEvent]) -> Result<(), StorageError>;
+
+    /// История изменений пользователя в чате, от старых к новым.
+    fn user_events(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<SizeEvent>, StorageError>;
+
+    /// История изменений всех пользователей чата, от старых к новым.
+    fn chat_events(&mut self, chat_id: i64) -> Result<Vec<SizeEvent>, StorageError>;
+
+    /// Настройки чата; если их ещё не сохраняли — настройки по умолчанию.
+    fn chat_settings(&mut self, chat_id: i64) -> Result<ChatSettings, StorageError>;
+
+    fn save_chat_settings(&mut self, settings: &ChatSettings) -> Result<(), StorageError>;
+
+    /// Открытые пользователем достижения в порядке открытия.
+    fn user_achievements(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<UnlockedAchievement>, StorageError>;
+
+    fn save_achievement(&mut self, unlocked: &UnlockedAchievement) -> Result<(), StorageError>;
+
+    /// Итоги завершённых сезонов чата, от старых к новым.
+    fn chat_seasons(&mut self, chat_id: i64) -> Result<Vec<SeasonResult>, StorageError>;
+
+    /// Сохраняет итоги сезона. Повторное сохранение того же сезона заменяет запись.
+    fn save_season(&mut self, result: &SeasonResult) -> Result<(), StorageError>;
+
+    /// Отпечатки того, что показывалось в чате начиная с `since`.
+    fn seen_since(&mut self, chat_id: i64, kind: SeenKind, since: DateTime<Utc>) -> Result<Vec<String>, StorageError>;
+
+    /// Запоминает показ и забывает показы этого вида в чате, случившиеся раньше `forget_before`.
+    fn mark_seen(&mut self, item: &SeenItem, forget_before: DateTime<Utc>) -> Result<(), StorageError>;
+
+    /// Список фильмов чата в порядке добавления.
+    fn watchlist(&mut self, chat_id: i64) -> Result<Vec<WatchlistEntry>, StorageError>;
+
+    /// Добавляет фильм в список чата. Возвращает false, если он там уже был.
+    fn add_to_watchlist(&mut self, entry: &WatchlistEntry) -> Result<bool, StorageError>;
+
+    fn remove_from_watchlist(&mut self, chat_id: i64, movie_id: u64) -> Result<(), StorageError>;
+
+    fn save_movie_poll(&mut self, poll: &MoviePoll) -> Result<(), StorageError>;
+
+    /// Забирает голосование по id опроса: после закрытия оно больше не нужно.
+    fn take_movie_poll(&mut self, poll_id: &str) -> Result<Option<MoviePoll>, StorageError>;
+}
+
+/// Все данные хранилища разом, для переноса между хранилищами.
+#[derive(Default)]
+pub struct Snapshot {
+    pub users: Vec<UserData>,
+    /// Пользователи из старого формата, ещё не привязанные к чату
+    pub legacy: Vec<UserData>,
+    pub history: Vec<SizeEvent>,
+    pub settings: Vec<ChatSettings>,
+    pub achievements: Vec<UnlockedAchievement>,
+    pub seasons: Vec<SeasonResult>,
+    pub seen: Vec<SeenItem>,
+    pub watchlist: Vec<WatchlistEntry>,
+    pub movie_polls: Vec<MoviePoll>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    Json,
+    Sqlite,
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "json" => Some(Backend::Json),
+            "sqlite" => Some(Backend::Sqlite),
+            _ => None,
+        }
+    }
+
+    pub fn default_path(self) -> &'static str {
+        match self {
+            Backend::Json => "users.json",
+            Backend::Sqlite => "users.db",
+        }
+    }
+}
+
+/// Открывает хранилище выбранного типа.
+pub fn open(backend: Backend, path: &str, backups: BackupPolicy) -> Result<Box<dyn Storage>, StorageError> {
+    match backend {
+        Backend::Json => Ok(Box::new(JsonStorage::open(path, backups)?)),
+        Backend::Sqlite => {
+            let mut storage = SqliteStorage::open(path)?;
+            import_json(&mut storage, backups)?;
+            Ok(Box::new(storage))
+        }
+    }
+}
+
+// При переходе на SQLite забираем данные из users.json, если база ещё пустая
+fn import_json(storage: &mut SqliteStorage, backups: BackupPolicy) -> Result<(), StorageError> {
+    let json_path = Backend::Json.default_path();
+    if !storage.is_empty()? || !std::path::Path::new(json_path).exists() {
+        return Ok(());
+    }
+
+    let snapshot = JsonStorage::open(json_path, backups)?.into_snapshot();
+    storage.import(&snapshot)?;
+    log::info!(
+        "Импортировано из {}: {} пользователей, {} без чата, {} событий истории, {} чатов с настройками, {} достижений, {} сезонов, {} показов, {} фильмов в списках, {} голосований",
+        json_path, snapshot.users.len(), snapshot.legacy.len(), snapshot.history.len(), snapshot.settings.len(),
+        snapshot.achievements.len(), snapshot.seasons.len(), snapshot.seen.len(), snapshot.watchlist.len(), snapshot.movie_polls.len()
+    );
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum StorageError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    Sqlite(rusqlite::Error),
+    Migration(String),
+    /// Данные записаны более новой версией бота
+    UnsupportedVersion { found: u32, supported: u32 },
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Io(e) => write!(f, "ошибка ввода-вывода: {}", e),
+            StorageError::Json(e) => write!(f, "ошибка JSON: {}", e),
+            StorageError::Sqlite(e) => write!(f, "ошибка SQLite: {}", e),
+            StorageError::Migration(e) => write!(f, "ошибка миграции данных: {}", e),
+            StorageError::UnsupportedVersion { found, supported } => {
+                write!(f, "данные версии {} записаны более новой версией бота (поддерживаются до {})", found, supported)
+            }
+        }
+    }
+}
+
+impl std::error::Error for StorageError {}
+
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self { StorageError::Io(e) }
+}
+
+impl From<serde_json::Error> for StorageError {
+    fn from(e: serde_json::Error) -> Self { StorageError::Json(e) }
+}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self { StorageError::Sqlite(e) }
+}
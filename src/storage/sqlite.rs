@@ -0,0 +1,662 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use crate::models::{
+    Achievement, ChangeSource, ChatSettings, Location, MoviePoll, SeasonResult, SeenItem, SeenKind, SizeEvent, Streak,
+    UnlockedAchievement, UserData, WatchlistEntry,
+};
+use super::{Snapshot, Storage, StorageError};
+
+/// Схема базы по версиям. Номер применённой версии хранится в `PRAGMA user_version`,
+/// при открытии выполняются только недостающие шаги.
+const MIGRATIONS: &[&str] = &[
+    // 1: пользователи по чатам и непривязанные записи из старого users.json
+    "
+    CREATE TABLE IF NOT EXISTS users (
+        chat_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        pisun INTEGER NOT NULL,
+        last_command TEXT NOT NULL,
+        last_train TEXT NOT NULL,
+        PRIMARY KEY (chat_id, user_id)
+    );
+    CREATE TABLE IF NOT EXISTS legacy_users (
+        user_id INTEGER PRIMARY KEY,
+        pisun INTEGER NOT NULL,
+        last_command TEXT NOT NULL,
+        last_train TEXT NOT NULL
+    );
+    ",
+    // 2: история изменений размера
+    "
+    CREATE TABLE size_events (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        chat_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        at TEXT NOT NULL,
+        source TEXT NOT NULL,
+        delta INTEGER NOT NULL,
+        size INTEGER NOT NULL
+    );
+    CREATE INDEX size_events_user ON size_events (chat_id, user_id, at);
+    ",
+    // 3: имена пользователей для рейтинга
+    "
+    ALTER TABLE users ADD COLUMN username TEXT;
+    ALTER TABLE users ADD COLUMN first_name TEXT;
+    ALTER TABLE users ADD COLUMN last_name TEXT;
+    ",
+    // 4: настройки чатов
+    "
+    CREATE TABLE chat_settings (
+        chat_id INTEGER PRIMARY KEY,
+        timezone TEXT,
+        reset_hour INTEGER
+    );
+    ",
+    // 5: откат между дуэлями
+    "
+    ALTER TABLE users ADD COLUMN last_duel TEXT;
+    ",
+    // 6: открытые достижения
+    "
+    CREATE TABLE achievements (
+        chat_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        achievement TEXT NOT NULL,
+        at TEXT NOT NULL,
+        PRIMARY KEY (chat_id, user_id, achievement)
+    );
+    ",
+    // 7: серии замеров и тренировок
+    "
+    ALTER TABLE users ADD COLUMN roll_streak INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE users ADD COLUMN best_roll_streak INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE users ADD COLUMN train_streak INTEGER NOT NULL DEFAULT 0;
+    ALTER TABLE users ADD COLUMN best_train_streak INTEGER NOT NULL DEFAULT 0;
+    ",
+    // 8: сезоны; итоговая таблица хранится как JSON
+    "
+    ALTER TABLE chat_settings ADD COLUMN season_start TEXT;
+    CREATE TABLE seasons (
+        chat_id INTEGER NOT NULL,
+        start TEXT NOT NULL,
+        end TEXT NOT NULL,
+        standings TEXT NOT NULL,
+        PRIMARY KEY (chat_id, start)
+    );
+    ",
+    // 9: язык ответов в чате
+    "
+    ALTER TABLE chat_settings ADD COLUMN language TEXT;
+    ",
+    // 10: город для погоды; все четыре поля заданы или пусты вместе
+    "
+    ALTER TABLE chat_settings ADD COLUMN location_name TEXT;
+    ALTER TABLE chat_settings ADD COLUMN latitude REAL;
+    ALTER TABLE chat_settings ADD COLUMN longitude REAL;
+    ALTER TABLE chat_settings ADD COLUMN location_timezone TEXT;
+    ",
+    // 11: недавние показы в чатах; повторный показ обновляет время
+    "
+    CREATE TABLE seen (
+        chat_id INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        key TEXT NOT NULL,
+        at TEXT NOT NULL,
+        PRIMARY KEY (chat_id, kind, key)
+    );
+    ",
+    // 12: списки фильмов и открытые голосования; фильмы опроса хранятся как JSON
+    "
+    CREATE TABLE watchlist (
+        chat_id INTEGER NOT NULL,
+        movie_id INTEGER NOT NULL,
+        title TEXT NOT NULL,
+        added_by INTEGER NOT NULL,
+        added_at TEXT NOT NULL,
+        PRIMARY KEY (chat_id, movie_id)
+    );
+    CREATE TABLE movie_polls (
+        poll_id TEXT PRIMARY KEY,
+        chat_id INTEGER NOT NULL,
+        message_id INTEGER NOT NULL,
+        movie_ids TEXT NOT NULL,
+        started_at TEXT NOT NULL
+    );
+    ",
+];
+
+/// Хранилище во встроенной базе SQLite. Каждое сохранение обновляет одну строку.
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(path: &str) -> Result<Self, StorageError> {
+        let mut conn = Connection::open(path)?;
+        migrate(&mut conn)?;
+        Ok(Self { conn })
+    }
+
+    pub fn is_empty(&self) -> Result<bool, StorageError> {
+        let count: i64 = self.conn.query_row(
+            "SELECT (SELECT COUNT(*) FROM users) + (SELECT COUNT(*) FROM legacy_users)",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(count == 0)
+    }
+
+    /// Переносит данные из другого хранилища одной транзакцией.
+    pub fn import(&mut self, snapshot: &Snapshot) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        for user in &snapshot.users {
+            upsert_user(&tx, user)?;
+        }
+        for user in &snapshot.legacy {
+            tx.execute(
+                "INSERT OR REPLACE INTO legacy_users (user_id, pisun, last_command, last_train)
+                 VALUES (?1, ?2, ?3, ?4)",
+                params![user.user_id, user.pisun, user.last_command, user.last_train],
+            )?;
+        }
+        for event in &snapshot.history {
+            insert_event(&tx, event)?;
+        }
+        for settings in &snapshot.settings {
+            upsert_settings(&tx, settings)?;
+        }
+        for unlocked in &snapshot.achievements {
+            insert_achievement(&tx, unlocked)?;
+        }
+        for result in &snapshot.seasons {
+            upsert_season(&tx, result)?;
+        }
+        for item in &snapshot.seen {
+            upsert_seen(&tx, item)?;
+        }
+        for entry in &snapshot.watchlist {
+            insert_watchlist_entry(&tx, entry)?;
+        }
+        for poll in &snapshot.movie_polls {
+            insert_movie_poll(&tx, poll)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), StorageError> {
+    let version = schema_version(conn)?;
+    if version > MIGRATIONS.len() {
+        return Err(StorageError::UnsupportedVersion { found: version as u32, supported: MIGRATIONS.len() as u32 });
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+        log::info!("База пользователей обновлена до версии {}", i + 1);
+    }
+    Ok(())
+}
+
+fn schema_version(conn: &Connection) -> rusqlite::Result<usize> {
+    let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    Ok(version as usize)
+}
+
+fn upsert_user(conn: &Connection, user: &UserData) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT INTO users (chat_id, user_id, pisun, last_command, last_train, username, first_name, last_name, last_duel,
+                            roll_streak, best_roll_streak, train_streak, best_train_streak)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+         ON CONFLICT (chat_id, user_id) DO UPDATE SET
+            pisun = excluded.pisun,
+            last_command = excluded.last_command,
+            last_train = excluded.last_train,
+            username = excluded.username,
+            first_name = excluded.first_name,
+            last_name = excluded.last_name,
+            last_duel = excluded.last_duel,
+            roll_streak = excluded.roll_streak,
+            best_roll_streak = excluded.best_roll_streak,
+            train_streak = excluded.train_streak,
+            best_train_streak = excluded.best_train_streak",
+        params![
+            user.chat_id, user.user_id, user.pisun, user.last_command, user.last_train,
+            user.username, user.first_name, user.last_name, user.last_duel,
+            user.roll_streak.current, user.roll_streak.best, user.train_streak.current, user.train_streak.best,
+        ],
+    )
+}
+
+fn insert_event(conn: &Connection, event: &SizeEvent) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT INTO size_events (chat_id, user_id, at, source, delta, size)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![event.chat_id, event.user_id, event.at, event.source.as_str(), event.delta, event.size],
+    )
+}
+
+fn event_from_row(row: &Row) -> rusqlite::Result<SizeEvent> {
+    let source: String = row.get("source")?;
+    Ok(SizeEvent {
+        chat_id: row.get("chat_id")?,
+        user_id: row.get("user_id")?,
+        at: row.get("at")?,
+        source: ChangeSource::from_name(&source).ok_or_else(|| {
+            rusqlite::Error::InvalidColumnType(0, format!("source = {}", source), rusqlite::types::Type::Text)
+        })?,
+        delta: row.get("delta")?,
+        size: row.get("size")?,
+    })
+}
+
+fn upsert_settings(conn: &Connection, settings: &ChatSettings) -> rusqlite::Result<usize> {
+    let location = settings.location.as_ref();
+    conn.execute(
+        "INSERT OR REPLACE INTO chat_settings
+            (chat_id, timezone, reset_hour, season_start, language, location_name, latitude, longitude, location_timezone)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            settings.chat_id,
+            settings.timezone,
+            settings.reset_hour,
+            settings.season_start,
+            settings.language,
+            location.map(|l| &l.name),
+            location.map(|l| l.latitude),
+            location.map(|l| l.longitude),
+            location.map(|l| &l.timezone),
+        ],
+    )
+}
+
+fn location_from_row(row: &Row) -> rusqlite::Result<Option<Location>> {
+    let name: Option<String> = row.get("location_name")?;
+    let latitude: Option<f64> = row.get("latitude")?;
+    let longitude: Option<f64> = row.get("longitude")?;
+    let timezone: Option<String> = row.get("location_timezone")?;
+    Ok(match (name, latitude, longitude, timezone) {
+        (Some(name), Some(latitude), Some(longitude), Some(timezone)) => Some(Location { name, latitude, longitude, timezone }),
+        _ => None,
+    })
+}
+
+fn insert_achievement(conn: &Connection, unlocked: &UnlockedAchievement) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR IGNORE INTO achievements (chat_id, user_id, achievement, at) VALUES (?1, ?2, ?3, ?4)",
+        params![unlocked.chat_id, unlocked.user_id, unlocked.achievement.as_str(), unlocked.at],
+    )
+}
+
+fn achievement_from_row(row: &Row) -> rusqlite::Result<UnlockedAchievement> {
+    let name: String = row.get("achievement")?;
+    Ok(UnlockedAchievement {
+        chat_id: row.get("chat_id")?,
+        user_id: row.get("user_id")?,
+        achievement: Achievement::from_name(&name).ok_or_else(|| {
+            rusqlite::Error::InvalidColumnType(0, format!("achievement = {}", name), rusqlite::types::Type::Text)
+        })?,
+        at: row.get("at")?,
+    })
+}
+
+fn upsert_season(conn: &Connection, result: &SeasonResult) -> Result<usize, StorageError> {
+    let standings = serde_json::to_string(&result.standings)?;
+    Ok(conn.execute(
+        "INSERT OR REPLACE INTO seasons (chat_id, start, end, standings) VALUES (?1, ?2, ?3, ?4)",
+        params![result.chat_id, result.start, result.end, standings],
+    )?)
+}
+
+fn upsert_seen(conn: &Connection, item: &SeenItem) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR REPLACE INTO seen (chat_id, kind, key, at) VALUES (?1, ?2, ?3, ?4)",
+        params![item.chat_id, item.kind.as_str(), item.key, item.at],
+    )
+}
+
+fn insert_watchlist_entry(conn: &Connection, entry: &WatchlistEntry) -> rusqlite::Result<usize> {
+    conn.execute(
+        "INSERT OR IGNORE INTO watchlist (chat_id, movie_id, title, added_by, added_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![entry.chat_id, entry.movie_id as i64, entry.title, entry.added_by, entry.added_at],
+    )
+}
+
+fn insert_movie_poll(conn: &Connection, poll: &MoviePoll) -> Result<usize, StorageError> {
+    let movie_ids = serde_json::to_string(&poll.movie_ids)?;
+    Ok(conn.execute(
+        "INSERT OR REPLACE INTO movie_polls (poll_id, chat_id, message_id, movie_ids, started_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![poll.poll_id, poll.chat_id, poll.message_id, movie_ids, poll.started_at],
+    )?)
+}
+
+fn user_from_row(row: &Row) -> rusqlite::Result<UserData> {
+    Ok(UserData {
+        chat_id: row.get("chat_id")?,
+        user_id: row.get("user_id")?,
+        pisun: row.get("pisun")?,
+        last_command: row.get("last_command")?,
+        last_train: row.get("last_train")?,
+        username: row.get("username")?,
+        first_name: row.get("first_name")?,
+        last_name: row.get("last_name")?,
+        last_duel: row.get("last_duel")?,
+        roll_streak: Streak {
+            current: row.get("roll_streak")?,
+            best: row.get("best_roll_streak")?,
+        },
+        train_streak: Streak {
+            current: row.get("train_streak")?,
+            best: row.get("best_train_streak")?,
+        },
+    })
+}
+
+impl Storage for SqliteStorage {
+    fn get_user(&mut self, chat_id: i64, user_id: i64) -> Result<Option<UserData>, StorageError> {
+        let user = self.conn.query_row(
+            "SELECT * FROM users WHERE chat_id = ?1 AND user_id = ?2",
+            params![chat_id, user_id],
+            user_from_row,
+        ).optional()?;
+        if user.is_some() {
+            return Ok(user);
+        }
+
+        // Непривязанная запись из старого формата достаётся первому чату
+        let tx = self.conn.transaction()?;
+        let legacy = tx.query_row(
+            "SELECT ?1 AS chat_id, NULL AS username, NULL AS first_name, NULL AS last_name, NULL AS last_duel,
+                    0 AS roll_streak, 0 AS best_roll_streak, 0 AS train_streak, 0 AS best_train_streak, *
+             FROM legacy_users WHERE user_id = ?2",
+            params![chat_id, user_id],
+            user_from_row,
+        ).optional()?;
+        if let Some(user) = &legacy {
+            upsert_user(&tx, user)?;
+            tx.execute("DELETE FROM legacy_users WHERE user_id = ?1", params![user_id])?;
+        }
+        tx.commit()?;
+        Ok(legacy)
+    }
+
+    fn save_user(&mut self, user: &UserData) -> Result<(), StorageError> {
+        upsert_user(&self.conn, user)?;
+        Ok(())
+    }
+
+    fn chat_users(&mut self, chat_id: i64) -> Result<Vec<UserData>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT * FROM users WHERE chat_id = ?1")?;
+        let users = stmt
+            .query_map(params![chat_id], user_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(users)
+    }
+
+    fn save_batch(&mut self, users: &[UserData], events: &[SizeEvent]) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        for user in users {
+            upsert_user(&tx, user)?;
+        }
+        for event in events {
+            insert_event(&tx, event)?;
+        }
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn user_events(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<SizeEvent>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM size_events WHERE chat_id = ?1 AND user_id = ?2 ORDER BY at, id",
+        )?;
+        let events = stmt
+            .query_map(params![chat_id, user_id], event_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    fn chat_events(&mut self, chat_id: i64) -> Result<Vec<SizeEvent>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM size_events WHERE chat_id = ?1 ORDER BY at, id",
+        )?;
+        let events = stmt
+            .query_map(params![chat_id], event_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(events)
+    }
+
+    fn chat_settings(&mut self, chat_id: i64) -> Result<ChatSettings, StorageError> {
+        let settings = self.conn.query_row(
+            "SELECT * FROM chat_settings WHERE chat_id = ?1",
+            params![chat_id],
+            |row| Ok(ChatSettings {
+                chat_id: row.get("chat_id")?,
+                timezone: row.get("timezone")?,
+                reset_hour: row.get("reset_hour")?,
+                season_start: row.get("season_start")?,
+                language: row.get("language")?,
+                location: location_from_row(row)?,
+            }),
+        ).optional()?;
+        Ok(settings.unwrap_or_else(|| ChatSettings::new(chat_id)))
+    }
+
+    fn save_chat_settings(&mut self, settings: &ChatSettings) -> Result<(), StorageError> {
+        upsert_settings(&self.conn, settings)?;
+        Ok(())
+    }
+
+    fn user_achievements(&mut self, chat_id: i64, user_id: i64) -> Result<Vec<UnlockedAchievement>, StorageError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT * FROM achievements WHERE chat_id = ?1 AND user_id = ?2 ORDER BY at",
+        )?;
+        let unlocked = stmt
+            .query_map(params![chat_id, user_id], achievement_from_row)?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(unlocked)
+    }
+
+    fn save_achievement(&mut self, unlocked: &UnlockedAchievement) -> Result<(), StorageError> {
+        insert_achievement(&self.conn, unlocked)?;
+        Ok(())
+    }
+
+    fn chat_seasons(&mut self, chat_id: i64) -> Result<Vec<SeasonResult>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT * FROM seasons WHERE chat_id = ?1 ORDER BY start")?;
+        let rows = stmt
+            .query_map(params![chat_id], |row| {
+                Ok((row.get("chat_id")?, row.get("start")?, row.get("end")?, row.get::<_, String>("standings")?))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        rows.into_iter()
+            .map(|(chat_id, start, end, standings)| Ok(SeasonResult {
+                chat_id,
+                start,
+                end,
+                standings: serde_json::from_str(&standings)?,
+            }))
+            .collect()
+    }
+
+    fn save_season(&mut self, result: &SeasonResult) -> Result<(), StorageError> {
+        upsert_season(&self.conn, result)?;
+        Ok(())
+    }
+
+    fn seen_since(&mut self, chat_id: i64, kind: SeenKind, since: DateTime<Utc>) -> Result<Vec<String>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT key FROM seen WHERE chat_id = ?1 AND kind = ?2 AND at >= ?3 ORDER BY at")?;
+        let keys = stmt
+            .query_map(params![chat_id, kind.as_str(), since], |row| row.get(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(keys)
+    }
+
+    fn mark_seen(&mut self, item: &SeenItem, forget_before: DateTime<Utc>) -> Result<(), StorageError> {
+        let tx = self.conn.transaction()?;
+        tx.execute(
+            "DELETE FROM seen WHERE chat_id = ?1 AND kind = ?2 AND at < ?3",
+            params![item.chat_id, item.kind.as_str(), forget_before],
+        )?;
+        upsert_seen(&tx, item)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    fn watchlist(&mut self, chat_id: i64) -> Result<Vec<WatchlistEntry>, StorageError> {
+        let mut stmt = self.conn.prepare("SELECT * FROM watchlist WHERE chat_id = ?1 ORDER BY added_at, rowid")?;
+        let entries = stmt
+            .query_map(params![chat_id], |row| Ok(WatchlistEntry {
+                chat_id: row.get("chat_id")?,
+                movie_id: row.get::<_, i64>("movie_id")? as u64,
+                title: row.get("title")?,
+                added_by: row.get("added_by")?,
+                added_at: row.get("added_at")?,
+            }))?
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    fn add_to_watchlist(&mut self, entry: &WatchlistEntry) -> Result<bool, StorageError> {
+        Ok(insert_watchlist_entry(&self.conn, entry)? > 0)
+    }
+
+    fn remove_from_watchlist(&mut self, chat_id: i64, movie_id: u64) -> Result<(), StorageError> {
+        self.conn.execute("DELETE FROM watchlist WHERE chat_id = ?1 AND movie_id = ?2", params![chat_id, movie_id as i64])?;
+        Ok(())
+    }
+
+    fn save_movie_poll(&mut self, poll: &MoviePoll) -> Result<(), StorageError> {
+        insert_movie_poll(&self.conn, poll)?;
+        Ok(())
+    }
+
+    fn take_movie_poll(&mut self, poll_id: &str) -> Result<Option<MoviePoll>, StorageError> {
+        let tx = self.conn.transaction()?;
+        let row = tx.query_row(
+            "SELECT * FROM movie_polls WHERE poll_id = ?1",
+            params![poll_id],
+            |row| Ok((row.get("chat_id")?, row.get("message_id")?, row.get::<_, String>("movie_ids")?, row.get("started_at")?)),
+        ).optional()?;
+        tx.execute("DELETE FROM movie_polls WHERE poll_id = ?1", params![poll_id])?;
+        tx.commit()?;
+
+        let Some((chat_id, message_id, movie_ids, started_at)) = row else {
+            return Ok(None);
+        };
+        Ok(Some(MoviePoll {
+            poll_id: poll_id.to_string(),
+            chat_id,
+            message_id,
+            movie_ids: serde_json::from_str(&movie_ids)?,
+            started_at,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn migrates_database_created_without_version() {
+        // База из первой версии хранилища: таблицы есть, user_version не выставлен
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.execute(
+            "INSERT INTO users (chat_id, user_id, pisun, last_command, last_train)
+             VALUES (1, 2, 5, '2024-11-20T18:04:11Z', '2024-11-20T18:04:11Z')",
+            [],
+        ).unwrap();
+
+        migrate(&mut conn).unwrap();
+
+        assert_eq!(schema_version(&conn).unwrap(), MIGRATIONS.len());
+        let mut storage = SqliteStorage { conn };
+        assert_eq!(storage.get_user(1, 2).unwrap().unwrap().pisun, 5);
+    }
+
+    #[test]
+    fn stores_season_standings() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        let date = |d| chrono::NaiveDate::from_ymd_opt(2024, 11, d).unwrap();
+        let mut result = SeasonResult {
+            chat_id: 1,
+            start: date(1),
+            end: date(30),
+            standings: vec![crate::models::SeasonStanding { user_id: 2, name: "Вася".to_string(), pisun: 42 }],
+        };
+        storage.save_season(&result).unwrap();
+        // Повторное сохранение того же сезона заменяет запись
+        result.standings[0].pisun = 43;
+        storage.save_season(&result).unwrap();
+
+        let seasons = storage.chat_seasons(1).unwrap();
+        assert_eq!(seasons.len(), 1);
+        assert_eq!((seasons[0].end, seasons[0].standings[0].pisun), (date(30), 43));
+    }
+
+    #[test]
+    fn stores_chat_location() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        let location = Location {
+            name: "Берлин, Германия".to_string(),
+            latitude: 52.52,
+            longitude: 13.41,
+            timezone: "Europe/Berlin".to_string(),
+        };
+        let mut settings = ChatSettings::new(1);
+        settings.location = Some(location.clone());
+        storage.save_chat_settings(&settings).unwrap();
+
+        assert_eq!(storage.chat_settings(1).unwrap().location, Some(location));
+        assert_eq!(storage.chat_settings(2).unwrap().location, None);
+    }
+
+    #[test]
+    fn remembers_and_forgets_seen_items() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        let day = |d| "2024-11-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + chrono::Duration::days(d);
+        let item = |key: &str, at| SeenItem { chat_id: 1, kind: SeenKind::Joke, key: key.to_string(), at };
+
+        storage.mark_seen(&item("a", day(0)), day(-30)).unwrap();
+        storage.mark_seen(&item("b", day(5)), day(-25)).unwrap();
+        assert_eq!(storage.seen_since(1, SeenKind::Joke, day(1)).unwrap(), ["b"]);
+
+        // Показ через месяц забывает всё, что старше окна
+        storage.mark_seen(&item("a", day(31)), day(1)).unwrap();
+        assert_eq!(storage.seen_since(1, SeenKind::Joke, day(0)).unwrap(), ["b", "a"]);
+        assert!(storage.seen_since(2, SeenKind::Joke, day(0)).unwrap().is_empty());
+    }
+
+    #[test]
+    fn keeps_watchlist_and_polls() {
+        let mut storage = SqliteStorage::open(":memory:").unwrap();
+        let day = |d| "2024-11-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap() + chrono::Duration::days(d);
+        let entry = |movie_id, title: &str, d| WatchlistEntry { chat_id: 1, movie_id, title: title.to_string(), added_by: 2, added_at: day(d) };
+
+        assert!(storage.add_to_watchlist(&entry(20992, "Брат", 1)).unwrap());
+        assert!(storage.add_to_watchlist(&entry(1398, "Сталкер", 0)).unwrap());
+        assert!(!storage.add_to_watchlist(&entry(1398, "Сталкер", 2)).unwrap());
+        let titles: Vec<String> = storage.watchlist(1).unwrap().into_iter().map(|e| e.title).collect();
+        assert_eq!(titles, ["Сталкер", "Брат"]);
+        storage.remove_from_watchlist(1, 1398).unwrap();
+        assert_eq!(storage.watchlist(1).unwrap().len(), 1);
+        assert!(storage.watchlist(2).unwrap().is_empty());
+
+        let poll = MoviePoll { poll_id: "42".to_string(), chat_id: 1, message_id: 7, movie_ids: vec![20992, 1398], started_at: day(3) };
+        storage.save_movie_poll(&poll).unwrap();
+        assert_eq!(storage.take_movie_poll("42").unwrap(), Some(poll));
+        assert_eq!(storage.take_movie_poll("42").unwrap(), None);
+    }
+
+    #[test]
+    fn rejects_newer_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", MIGRATIONS.len() as i64 + 1).unwrap();
+        assert!(matches!(migrate(&mut conn), Err(StorageError::UnsupportedVersion { .. })));
+    }
+}
@@ -0,0 +1,270 @@
+//! Погода из Open-Meteo: поиск города по названию и прогноз для выбранного места.
+//! Ответы разбираются в типизированные структуры, любая неполадка API превращается в [`WeatherError`].
+
+use std::fmt;
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use crate::http::{FetchError, HttpClient, Source};
+use crate::i18n::Lang;
+use crate::models::Location;
+
+const GEOCODING_URL: &str = "https://geocoding-api.open-meteo.com/v1/search";
+const FORECAST_URL: &str = "https://api.open-meteo.com/v1/forecast";
+
+/// Место для чатов, которые не выбирали город: раньше бот показывал погоду только в Омске.
+pub fn default_location(name: String) -> Location {
+    Location {
+        name,
+        latitude: 55.0,
+        longitude: 73.70,
+        timezone: "Asia/Omsk".to_string(),
+    }
+}
+
+#[derive(Debug)]
+pub enum WeatherError {
+    Fetch(FetchError),
+    Parse(serde_json::Error),
+    /// Ответ разобрался, но в нём не хватает данных, например прогноза на сегодня
+    Incomplete(&'static str),
+}
+
+impl fmt::Display for WeatherError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WeatherError::Fetch(e) => write!(f, "Open-Meteo: {}", e),
+            WeatherError::Parse(e) => write!(f, "неожиданный ответ: {}", e),
+            WeatherError::Incomplete(what) => write!(f, "в ответе нет {}", what),
+        }
+    }
+}
+
+impl std::error::Error for WeatherError {}
+
+impl From<FetchError> for WeatherError {
+    fn from(e: FetchError) -> Self {
+        WeatherError::Fetch(e)
+    }
+}
+
+/// Погода сейчас, на сегодня и на следующие дни; время — в часовом поясе места.
+#[derive(Debug)]
+pub struct Weather {
+    pub current: Current,
+    pub today: Day,
+    pub next_days: Vec<Day>,
+    /// Наибольшая вероятность осадков за ближайшие сутки, %
+    pub precipitation_probability: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Current {
+    #[serde(rename = "temperature_2m")]
+    pub temperature: f64,
+    #[serde(rename = "apparent_temperature")]
+    pub apparent: f64,
+    #[serde(rename = "relative_humidity_2m")]
+    pub humidity: f64,
+    #[serde(rename = "windspeed_10m")]
+    pub wind_speed: f64,
+    #[serde(rename = "is_day", deserialize_with = "flag")]
+    pub daytime: bool,
+    #[serde(rename = "weathercode")]
+    pub code: i64,
+}
+
+#[derive(Debug)]
+pub struct Day {
+    pub date: DateTime<Tz>,
+    pub min: f64,
+    pub max: f64,
+    pub sunrise: DateTime<Tz>,
+    pub sunset: DateTime<Tz>,
+    pub code: i64,
+}
+
+// Open-Meteo отдаёт признаки числами 0 и 1
+fn flag<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    Ok(i64::deserialize(deserializer)? != 0)
+}
+
+#[derive(Deserialize)]
+struct ForecastResponse {
+    current: Current,
+    hourly: Hourly,
+    daily: Daily,
+}
+
+#[derive(Deserialize)]
+struct Hourly {
+    // На часы без прогноза приходит null
+    precipitation_probability: Vec<Option<i64>>,
+}
+
+#[derive(Deserialize)]
+struct Daily {
+    time: Vec<i64>,
+    temperature_2m_max: Vec<f64>,
+    temperature_2m_min: Vec<f64>,
+    sunrise: Vec<i64>,
+    sunset: Vec<i64>,
+    weathercode: Vec<i64>,
+}
+
+impl Daily {
+    fn day(&self, i: usize, timezone: Tz) -> Option<Day> {
+        let time = |timestamps: &[i64]| Some(DateTime::from_timestamp(*timestamps.get(i)?, 0)?.with_timezone(&timezone));
+        Some(Day {
+            date: time(&self.time)?,
+            min: *self.temperature_2m_min.get(i)?,
+            max: *self.temperature_2m_max.get(i)?,
+            sunrise: time(&self.sunrise)?,
+            sunset: time(&self.sunset)?,
+            code: *self.weathercode.get(i)?,
+        })
+    }
+}
+
+/// Разбирает ответ прогноза. Отдельно от запроса, чтобы проверять на записанных ответах.
+pub fn parse_forecast(text: &str, timezone: Tz) -> Result<Weather, WeatherError> {
+    let response: ForecastResponse = serde_json::from_str(text).map_err(WeatherError::Parse)?;
+    let daily = response.daily;
+    let today = daily.day(0, timezone).ok_or(WeatherError::Incomplete("прогноза на сегодня"))?;
+    let next_days = (1..daily.time.len()).map_while(|i| daily.day(i, timezone)).collect();
+    let precipitation_probability = response.hourly.precipitation_probability
+        .iter()
+        .take(24)
+        .flatten()
+        .copied()
+        .max()
+        .unwrap_or(0);
+    Ok(Weather { current: response.current, today, next_days, precipitation_probability })
+}
+
+/// Прогноз на три дня для места.
+pub async fn forecast(http: &HttpClient, location: &Location) -> Result<Weather, WeatherError> {
+    let timezone: Tz = location.timezone.parse().unwrap_or(Tz::UTC);
+    let body = http.get(Source::Weather, &forecast_url(location), &[]).await?;
+    parse_forecast(&body, timezone)
+}
+
+/// Сейчас в месте, для заголовка сводки.
+pub fn local_now(location: &Location) -> DateTime<Tz> {
+    Utc::now().with_timezone(&location.timezone.parse().unwrap_or(Tz::UTC))
+}
+
+#[derive(Deserialize)]
+struct GeocodingResponse {
+    // Если ничего не нашлось, поля нет совсем
+    #[serde(default)]
+    results: Vec<Place>,
+}
+
+#[derive(Deserialize)]
+struct Place {
+    name: String,
+    latitude: f64,
+    longitude: f64,
+    timezone: String,
+    country: Option<String>,
+    /// Регион: область, штат или земля
+    admin1: Option<String>,
+}
+
+impl Place {
+    // «Город, регион, страна»; регион пропускается, если совпадает с названием (Москва, Берлин)
+    fn into_location(self) -> Location {
+        let mut parts = vec![self.name.clone()];
+        parts.extend(self.admin1.filter(|region| *region != self.name));
+        parts.extend(self.country);
+        Location {
+            name: parts.join(", "),
+            latitude: self.latitude,
+            longitude: self.longitude,
+            timezone: self.timezone,
+        }
+    }
+}
+
+/// Ищет город по названию и возвращает самый подходящий. Название места приходит на языке чата.
+pub async fn geocode(http: &HttpClient, query: &str, lang: Lang) -> Result<Option<Location>, WeatherError> {
+    let query = [("name", query), ("count", "1"), ("language", lang.code()), ("format", "json")];
+    let body = http.get(Source::Geocoding, GEOCODING_URL, &query).await?;
+    let response: GeocodingResponse = serde_json::from_str(&body).map_err(WeatherError::Parse)?;
+    Ok(response.results.into_iter().next().map(Place::into_location))
+}
+
+// Время в ответе — метки Unix, дни считаются по часовому поясу места
+fn forecast_url(location: &Location) -> String {
+    format!(
+        "{}?latitude={}&longitude={}&current=temperature_2m,relative_humidity_2m,apparent_temperature,is_day,precipitation,rain,showers,snowfall,weathercode,windspeed_10m&hourly=temperature_2m,precipitation_probability,weathercode&daily=temperature_2m_max,temperature_2m_min,sunrise,sunset,weathercode&wind_speed_unit=ms&timeformat=unixtime&timezone={}&forecast_days=3",
+        FORECAST_URL, location.latitude, location.longitude, location.timezone
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn fixture(name: &str) -> String {
+        std::fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name)).unwrap()
+    }
+
+    #[test]
+    fn parses_recorded_forecast() {
+        let weather = parse_forecast(&fixture("open_meteo_forecast.json"), chrono_tz::Asia::Omsk).unwrap();
+        assert_eq!(weather.current.temperature, 12.4);
+        assert_eq!(weather.current.humidity, 71.0);
+        assert!(weather.current.daytime);
+        assert_eq!(weather.current.code, 3);
+        // null в часовом прогнозе пропускается
+        assert_eq!(weather.precipitation_probability, 45);
+        assert_eq!(weather.today.sunrise.format("%d.%m %H:%M").to_string(), "18.10 07:50");
+        assert_eq!(weather.next_days.len(), 2);
+        assert_eq!(weather.next_days[1].date.format("%d.%m").to_string(), "20.10");
+        assert_eq!(weather.next_days[1].code, 71);
+    }
+
+    #[test]
+    fn broken_responses_are_errors() {
+        let mut missing_day: serde_json::Value = serde_json::from_str(&fixture("open_meteo_forecast.json")).unwrap();
+        for field in ["time", "temperature_2m_max", "temperature_2m_min", "sunrise", "sunset", "weathercode"] {
+            missing_day["daily"][field] = serde_json::json!([]);
+        }
+        let result = parse_forecast(&missing_day.to_string(), Tz::UTC);
+        assert!(matches!(result, Err(WeatherError::Incomplete(_))));
+
+        let result = parse_forecast(r#"{"error": true, "reason": "Cannot initialize WeatherVariable"}"#, Tz::UTC);
+        assert!(matches!(result, Err(WeatherError::Parse(_))));
+    }
+
+    #[test]
+    fn parses_geocoding_results() {
+        let response: GeocodingResponse = serde_json::from_str(
+            r#"{"results": [{"id": 1496153, "name": "Омск", "latitude": 54.99244, "longitude": 73.36859,
+                "timezone": "Asia/Omsk", "country": "Россия", "admin1": "Омская область"}],
+                "generationtime_ms": 0.5}"#,
+        ).unwrap();
+        let location = response.results.into_iter().next().map(Place::into_location).unwrap();
+        assert_eq!(location.name, "Омск, Омская область, Россия");
+        assert_eq!(location.timezone, "Asia/Omsk");
+
+        let empty: GeocodingResponse = serde_json::from_str(r#"{"generationtime_ms": 0.3}"#).unwrap();
+        assert!(empty.results.is_empty());
+    }
+
+    #[test]
+    fn region_named_like_city_is_skipped() {
+        let place = Place {
+            name: "Berlin".to_string(),
+            latitude: 52.52,
+            longitude: 13.41,
+            timezone: "Europe/Berlin".to_string(),
+            country: Some("Germany".to_string()),
+            admin1: Some("Berlin".to_string()),
+        };
+        assert_eq!(place.into_location().name, "Berlin, Germany");
+    }
+}